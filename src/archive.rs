@@ -0,0 +1,703 @@
+//! A multi-file archive format with a central directory.
+//!
+//! Every entry of an archive is compressed as its own LZHAM stream, and a central directory
+//! at the end of the archive records where each entry is and how it was compressed. Reading
+//! an entry only decompresses that entry, and entries can be appended to an existing
+//! archive without rewriting the entries already in it.
+//!
+//! Small entries compress poorly on their own, as they leave most of the dictionary unused.
+//! In solid mode, enabled with [`ArchiveWriter::set_solid_block_size`], consecutive entries
+//! are grouped into solid blocks, each compressed as a single stream. Reading an entry of a
+//! solid block decompresses the whole block, but only that block, and the last block is
+//! kept so the other entries of the block are read without decompressing it again.
+//!
+//! ## Format
+//!
+//! An archive starts with the magic bytes `LZHA`, followed by the format version, `1`. Then
+//! come the compressed entries, the central directory, and a 28 bytes footer:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 8    | The offset of the central directory     |
+//! | 8      | 8    | The size of the central directory       |
+//! | 16     | 4    | The number of entries                   |
+//! | 20     | 4    | The CRC-32 of the central directory     |
+//! | 24     | 4    | The magic bytes `LZHA`                  |
+//!
+//! The central directory holds a record for each entry, in the order they were added:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 2    | The length of the name                  |
+//! | 2      | n    | The name, in UTF-8                      |
+//! | n + 2  | 8    | The offset of the compressed stream     |
+//! | n + 10 | 8    | The size of the compressed stream       |
+//! | n + 18 | 8    | The size of the uncompressed data       |
+//! | n + 26 | 4    | The CRC-32 of the uncompressed data     |
+//! | n + 30 | 1    | The base 2 log of the dictionary size   |
+//! | n + 31 | 1    | The level of compression, from 0 to 4   |
+//! | n + 32 | 1    | The table update rate                   |
+//! | n + 33 | 1    | The flags of the entry                  |
+//! | n + 34 | 8    | The table update interval, or zero      |
+//!
+//! Integers are big-endian. The only flag is `0x01`, set for an entry of a solid block. The
+//! stream is then the stream of the whole block, and the record ends with 16 more bytes:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | n + 42 | 8    | The offset of the entry in the block    |
+//! | n + 50 | 8    | The size of the uncompressed block      |
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::archive::{ArchiveReader, ArchiveWriter};
+//! # use std::io::Cursor;
+//! let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+//! writer.add("a.txt", b"This is a test.").unwrap();
+//! writer.add("b.txt", b"This is another test.").unwrap();
+//! let archive = writer.finish().unwrap();
+//!
+//! let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+//!
+//! assert_eq!(reader.read("b.txt").unwrap(), b"This is another test.");
+//! ```
+
+use crate::{
+    compress::{CompressionLevel, Compressor},
+    decompress::{DecompressionFlag, Decompressor},
+    low::{crc32, MAX_DICT_SIZE_LOG2, MIN_DICT_SIZE_LOG2},
+    CompressionOptions, DecompressionOptions, Error, Result, TableUpdateInterval, TableUpdateRate,
+};
+use std::{
+    collections::HashMap,
+    convert::{TryFrom, TryInto},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+/// The bytes starting every archive.
+pub(crate) const MAGIC: [u8; 5] = [b'L', b'Z', b'H', b'A', 1];
+
+/// The size of the footer.
+const FOOTER_SIZE: usize = 28;
+
+/// The size of a directory record, without the name and the solid block position.
+const RECORD_SIZE: usize = 42;
+
+/// The size of the solid block position in a directory record.
+const SOLID_SIZE: usize = 16;
+
+/// The flag of the entries of a solid block.
+const FLAG_SOLID: u8 = 0x01;
+
+/// The position of an entry in a solid block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SolidPosition {
+    /// The offset of the entry in the uncompressed block.
+    pub offset: u64,
+    /// The size of the uncompressed block.
+    pub block_size: u64,
+}
+
+/// An entry of an archive.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveEntry {
+    /// The name of the entry.
+    pub name: String,
+    /// The offset of the compressed stream in the archive.
+    ///
+    /// For an entry of a solid block, it is the stream of the block.
+    pub offset: u64,
+    /// The size of the compressed stream.
+    pub compressed_size: u64,
+    /// The size of the uncompressed data.
+    pub uncompressed_size: u64,
+    /// The CRC-32 of the uncompressed data.
+    pub crc32: u32,
+    /// The base 2 log of the dictionary size.
+    pub dict_size_log2: u32,
+    /// The level of compression.
+    pub compression_level: CompressionLevel,
+    /// The table update rate.
+    pub table_update_rate: TableUpdateRate,
+    /// The table update interval, if it overrides the rate.
+    pub table_update_interval: Option<TableUpdateInterval>,
+    /// The position of the entry in its solid block, if it is in one.
+    pub solid: Option<SolidPosition>,
+}
+
+impl ArchiveEntry {
+    /// Returns the options to decompress the entry.
+    pub fn decompression_options(&self) -> DecompressionOptions {
+        DecompressionOptions {
+            dict_size_log2: self.dict_size_log2,
+            table_update_rate: self.table_update_rate,
+            table_update_interval: self.table_update_interval,
+            decompression_flags: Some(DecompressionFlag::ComputeAdler32),
+            ..Default::default()
+        }
+    }
+
+    fn write(&self, output: &mut Vec<u8>) {
+        let level = match self.compression_level {
+            CompressionLevel::Fastest => 0,
+            CompressionLevel::Faster => 1,
+            CompressionLevel::Default => 2,
+            CompressionLevel::Better => 3,
+            CompressionLevel::Uber => 4,
+        };
+        let TableUpdateInterval(max_interval, slow_rate) = self
+            .table_update_interval
+            .unwrap_or(TableUpdateInterval(0, 0));
+
+        output.extend_from_slice(&(self.name.len() as u16).to_be_bytes());
+        output.extend_from_slice(self.name.as_bytes());
+        output.extend_from_slice(&self.offset.to_be_bytes());
+        output.extend_from_slice(&self.compressed_size.to_be_bytes());
+        output.extend_from_slice(&self.uncompressed_size.to_be_bytes());
+        output.extend_from_slice(&self.crc32.to_be_bytes());
+        output.extend_from_slice(&[
+            self.dict_size_log2 as u8,
+            level,
+            self.table_update_rate.0 as u8,
+            if self.solid.is_some() { FLAG_SOLID } else { 0 },
+        ]);
+        output.extend_from_slice(&max_interval.to_be_bytes());
+        output.extend_from_slice(&slow_rate.to_be_bytes());
+
+        if let Some(solid) = self.solid {
+            output.extend_from_slice(&solid.offset.to_be_bytes());
+            output.extend_from_slice(&solid.block_size.to_be_bytes());
+        }
+    }
+
+    /// Reads a record at the start of `input`, and returns it along with its size.
+    fn read(input: &[u8]) -> Result<(Self, usize)> {
+        let invalid = || Error::InvalidFormat("invalid archive directory");
+
+        let name_len = u16::from_be_bytes(input.get(..2).ok_or_else(invalid)?.try_into().unwrap());
+        let mut size = name_len as usize + RECORD_SIZE;
+        let name = input.get(2..2 + name_len as usize).ok_or_else(invalid)?;
+        let record = input.get(2 + name_len as usize..size).ok_or_else(invalid)?;
+
+        let solid = match record[31] {
+            0 => None,
+            FLAG_SOLID => {
+                let position = input.get(size..size + SOLID_SIZE).ok_or_else(invalid)?;
+                size += SOLID_SIZE;
+
+                Some(SolidPosition {
+                    offset: be_u64(&position[..8]),
+                    block_size: be_u64(&position[8..]),
+                })
+            }
+            _ => return Err(invalid()),
+        };
+
+        let compression_level = match record[29] {
+            0 => CompressionLevel::Fastest,
+            1 => CompressionLevel::Faster,
+            2 => CompressionLevel::Default,
+            3 => CompressionLevel::Better,
+            4 => CompressionLevel::Uber,
+            _ => return Err(invalid()),
+        };
+        let dict_size_log2 = match record[28] as u32 {
+            dict_size_log2 @ MIN_DICT_SIZE_LOG2..=MAX_DICT_SIZE_LOG2 => dict_size_log2,
+            _ => return Err(invalid()),
+        };
+        let table_update_interval = match (be_u32(&record[32..36]), be_u32(&record[36..40])) {
+            (0, 0) => None,
+            (max_interval, slow_rate) => Some(TableUpdateInterval(max_interval, slow_rate)),
+        };
+
+        let entry = Self {
+            name: String::from_utf8(name.to_vec()).map_err(|_| invalid())?,
+            offset: be_u64(&record[..8]),
+            compressed_size: be_u64(&record[8..16]),
+            uncompressed_size: be_u64(&record[16..24]),
+            crc32: be_u32(&record[24..28]),
+            dict_size_log2,
+            compression_level,
+            table_update_rate: TableUpdateRate::new(record[30] as u32),
+            table_update_interval,
+            solid,
+        };
+
+        Ok((entry, size))
+    }
+}
+
+/// Writes an archive into a [`Write`].
+///
+/// [`finish`] must be called to write the central directory, otherwise the archive cannot
+/// be read.
+///
+/// [`finish`]: ArchiveWriter::finish
+#[derive(Debug)]
+pub struct ArchiveWriter<W: Write> {
+    inner: W,
+    options: CompressionOptions,
+    compressor: Option<Compressor>,
+    entries: Vec<ArchiveEntry>,
+    offset: u64,
+    solid_block_size: Option<usize>,
+    block: Vec<u8>,
+    block_options: CompressionOptions,
+    block_entries: Vec<ArchiveEntry>,
+}
+
+impl<W: Write> ArchiveWriter<W> {
+    /// Creates a new archive with default [`options`], and writes its header.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn new(inner: W) -> Result<Self> {
+        Self::with_options(inner, CompressionOptions::default())
+    }
+
+    /// Creates a new archive whose entries are compressed with provided [`options`] by
+    /// default, and writes its header.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn with_options(mut inner: W, options: CompressionOptions) -> Result<Self> {
+        inner.write_all(&MAGIC)?;
+
+        Ok(Self::from_parts(
+            inner,
+            options,
+            Vec::new(),
+            MAGIC.len() as u64,
+        ))
+    }
+
+    fn from_parts(
+        inner: W,
+        options: CompressionOptions,
+        entries: Vec<ArchiveEntry>,
+        offset: u64,
+    ) -> Self {
+        Self {
+            inner,
+            block_options: options.clone(),
+            options,
+            compressor: None,
+            entries,
+            offset,
+            solid_block_size: None,
+            block: Vec::new(),
+            block_entries: Vec::new(),
+        }
+    }
+
+    /// Returns the entries written so far, including the ones already in an appended
+    /// archive.
+    ///
+    /// The entries of the current solid block are only included once the block is written.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Returns the size of the solid blocks, or `None` if solid mode is disabled.
+    pub fn solid_block_size(&self) -> Option<usize> {
+        self.solid_block_size
+    }
+
+    /// Sets the size of the solid blocks, or disables solid mode with `None`.
+    ///
+    /// Entries are added to the current block until its uncompressed data reaches
+    /// `block_size`. An entry larger than that gets a block of its own. Larger blocks
+    /// compress better, but reading any entry decompresses its whole block. Solid mode is
+    /// disabled by default.
+    pub fn set_solid_block_size(&mut self, block_size: Option<usize>) {
+        self.solid_block_size = block_size.map(|block_size| block_size.max(1));
+    }
+
+    /// Compresses `data` into a new entry, with the default options of the writer.
+    ///
+    /// If the archive already has an entry with the same name, the new one shadows it.
+    pub fn add(&mut self, name: &str, data: &[u8]) -> Result<()> {
+        let options = self.options.clone();
+
+        self.add_with_options(name, data, &options)
+    }
+
+    /// Compresses `data` into a new entry, with provided [`options`].
+    ///
+    /// The options are recorded in the central directory. Seed bytes are not supported, and
+    /// are ignored. In solid mode, an entry with other options than the current block starts
+    /// a new block.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn add_with_options(
+        &mut self,
+        name: &str,
+        data: &[u8],
+        options: &CompressionOptions,
+    ) -> Result<()> {
+        if name.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "entry name too long").into());
+        }
+
+        let options = CompressionOptions {
+            num_seed_bytes: None,
+            p_seed_bytes: None,
+            ..options.clone()
+        };
+
+        let mut entry = ArchiveEntry {
+            name: name.to_owned(),
+            offset: 0,
+            compressed_size: 0,
+            uncompressed_size: data.len() as u64,
+            crc32: crc32(0, data),
+            dict_size_log2: options.dict_size_log2,
+            compression_level: options.compression_level,
+            table_update_rate: options.table_update_rate,
+            table_update_interval: options.table_update_interval,
+            solid: None,
+        };
+
+        let block_size = match self.solid_block_size {
+            Some(block_size) => block_size,
+            None => {
+                // The entries are kept in the order they were added.
+                self.finish_block()?;
+
+                let (offset, compressed_size) = self.compress(data, &options)?;
+                entry.offset = offset;
+                entry.compressed_size = compressed_size;
+                self.entries.push(entry);
+
+                return Ok(());
+            }
+        };
+
+        if !self.block.is_empty()
+            && (self.block_options != options || self.block.len() + data.len() > block_size)
+        {
+            self.finish_block()?;
+        }
+
+        entry.solid = Some(SolidPosition {
+            offset: self.block.len() as u64,
+            block_size: 0,
+        });
+        self.block.extend_from_slice(data);
+        self.block_options = options;
+        self.block_entries.push(entry);
+
+        if self.block.len() >= block_size {
+            self.finish_block()?;
+        }
+
+        Ok(())
+    }
+
+    /// Compresses the current solid block, so the next entries start a new one.
+    ///
+    /// It does nothing if the block is empty or solid mode is disabled.
+    pub fn finish_block(&mut self) -> Result<()> {
+        if self.block_entries.is_empty() {
+            return Ok(());
+        }
+
+        let block = std::mem::take(&mut self.block);
+        let options = self.block_options.clone();
+        let (offset, compressed_size) = self.compress(&block, &options)?;
+
+        for mut entry in self.block_entries.drain(..) {
+            entry.offset = offset;
+            entry.compressed_size = compressed_size;
+            if let Some(solid) = &mut entry.solid {
+                solid.block_size = block.len() as u64;
+            }
+
+            self.entries.push(entry);
+        }
+
+        // The buffer is reused for the next block.
+        self.block = block;
+        self.block.clear();
+
+        Ok(())
+    }
+
+    /// Compresses `data` at the end of the archive, and returns its offset and size.
+    fn compress(&mut self, data: &[u8], options: &CompressionOptions) -> Result<(u64, u64)> {
+        // Creating a compressor is expensive, so it is reused while the options are the same.
+        let mut compressor = match self.compressor.take() {
+            Some(compressor) if compressor.options() == options => compressor,
+            _ => Compressor::from_options(options.clone()),
+        };
+        let report = compressor.compress_with_report(&mut &data[..], &mut self.inner);
+        if compressor.reinit() {
+            self.compressor = Some(compressor);
+        }
+        let report = report?;
+
+        let offset = self.offset;
+        self.offset += report.bytes_out;
+
+        Ok((offset, report.bytes_out))
+    }
+
+    /// Writes the current solid block and the central directory, and returns the underlying
+    /// writer.
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_block()?;
+
+        let mut directory = Vec::new();
+        for entry in &self.entries {
+            entry.write(&mut directory);
+        }
+
+        let mut footer = [0; FOOTER_SIZE];
+        footer[..8].copy_from_slice(&self.offset.to_be_bytes());
+        footer[8..16].copy_from_slice(&(directory.len() as u64).to_be_bytes());
+        footer[16..20].copy_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        footer[20..24].copy_from_slice(&crc32(0, &directory).to_be_bytes());
+        footer[24..].copy_from_slice(&MAGIC[..4]);
+
+        self.inner.write_all(&directory)?;
+        self.inner.write_all(&footer)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Read + Write + Seek> ArchiveWriter<W> {
+    /// Opens an existing archive to append entries to it, with default [`options`].
+    ///
+    /// The new entries are written over the central directory, and [`finish`] writes the
+    /// directory again after them, so the entries already in the archive are not rewritten.
+    /// Until [`finish`] returns, the archive cannot be read.
+    ///
+    /// [`options`]: CompressionOptions
+    /// [`finish`]: ArchiveWriter::finish
+    pub fn append(inner: W) -> Result<Self> {
+        Self::append_with_options(inner, CompressionOptions::default())
+    }
+
+    /// Opens an existing archive to append entries to it, with provided [`options`].
+    ///
+    /// See [`append`] for details.
+    ///
+    /// [`options`]: CompressionOptions
+    /// [`append`]: ArchiveWriter::append
+    pub fn append_with_options(mut inner: W, options: CompressionOptions) -> Result<Self> {
+        let (entries, offset) = read_directory(&mut inner)?;
+        inner.seek(SeekFrom::Start(offset))?;
+
+        Ok(Self::from_parts(inner, options, entries, offset))
+    }
+}
+
+/// Reads the entries of an archive from a [`Read`] and [`Seek`].
+///
+/// It only reads the central directory when it is created. Entries are read and
+/// decompressed on demand. It fails with [`Error::InvalidFormat`] if the archive or an
+/// entry is damaged.
+#[derive(Debug)]
+pub struct ArchiveReader<R: Read + Seek> {
+    inner: R,
+    entries: Vec<ArchiveEntry>,
+    index: HashMap<String, usize>,
+    decompressor: Option<Decompressor>,
+    block: Option<(u64, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> ArchiveReader<R> {
+    /// Opens an archive and reads its central directory.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let (entries, _) = read_directory(&mut inner)?;
+        let index = entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.name.clone(), i))
+            .collect();
+
+        Ok(Self {
+            inner,
+            entries,
+            index,
+            decompressor: None,
+            block: None,
+        })
+    }
+
+    /// Returns all the entries of the archive, in the order they were added.
+    ///
+    /// It includes the entries shadowed by a later entry with the same name.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// Returns the last entry added with the given name.
+    pub fn entry(&self, name: &str) -> Option<&ArchiveEntry> {
+        self.index.get(name).map(|&i| &self.entries[i])
+    }
+
+    /// Decompresses the last entry added with the given name.
+    ///
+    /// It fails with [`Error::EntryNotFound`] if the archive has no such entry.
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>> {
+        match self.index.get(name) {
+            Some(&i) => self.read_at(i),
+            None => Err(Error::EntryNotFound(name.to_owned())),
+        }
+    }
+
+    /// Decompresses the entry at `index` in [`entries`].
+    ///
+    /// # Panics
+    ///
+    /// It panics if `index` is out of bounds.
+    ///
+    /// [`entries`]: ArchiveReader::entries
+    pub fn read_at(&mut self, index: usize) -> Result<Vec<u8>> {
+        let entry = self.entries[index].clone();
+
+        let data = match entry.solid {
+            None => self.decompress(&entry, entry.uncompressed_size)?,
+            Some(solid) => {
+                // The entries of a block are often read together, so the last block is kept.
+                // Entries of a crafted archive may claim different sizes for the same block.
+                let block = match self.block.take() {
+                    Some((offset, block))
+                        if offset == entry.offset && block.len() as u64 == solid.block_size =>
+                    {
+                        block
+                    }
+                    _ => self.decompress(&entry, solid.block_size)?,
+                };
+
+                let range = usize::try_from(solid.offset).ok().and_then(|start| {
+                    let end = start.checked_add(usize::try_from(entry.uncompressed_size).ok()?)?;
+                    Some(start..end)
+                });
+                let data = range.and_then(|range| block.get(range)).map(<[u8]>::to_vec);
+                self.block = Some((entry.offset, block));
+
+                data.ok_or(Error::InvalidFormat("damaged archive entry"))?
+            }
+        };
+
+        if crc32(0, &data) != entry.crc32 {
+            return Err(Error::InvalidFormat("damaged archive entry"));
+        }
+
+        Ok(data)
+    }
+
+    /// Decompresses the stream of `entry`, which must be `size` bytes.
+    fn decompress(&mut self, entry: &ArchiveEntry, size: u64) -> Result<Vec<u8>> {
+        let damaged = || Error::InvalidFormat("damaged archive entry");
+
+        let compressed_size = usize::try_from(entry.compressed_size).map_err(|_| damaged())?;
+        let size = usize::try_from(size).map_err(|_| damaged())?;
+
+        let mut comp = vec![0; compressed_size];
+        self.inner.seek(SeekFrom::Start(entry.offset))?;
+        self.inner.read_exact(&mut comp)?;
+
+        let options = entry.decompression_options();
+        // Creating a decompressor is expensive, so it is reused while the options are the same.
+        let mut decompressor = match self.decompressor.take() {
+            Some(decompressor) if decompressor.options() == &options => decompressor,
+            _ => Decompressor::from_options(options),
+        };
+
+        // The sizes are not trusted for the allocation, as the archive may be damaged.
+        let mut output = Vec::with_capacity(size.min(comp.len().saturating_mul(4)));
+        let report = decompressor.decompress_to_vec_with_report(&comp, &mut output, size);
+        if decompressor.reset() {
+            self.decompressor = Some(decompressor);
+        }
+        let report = report?;
+
+        if report.bytes_in != entry.compressed_size || output.len() != size {
+            return Err(damaged());
+        }
+
+        Ok(output)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+/// Reads the central directory of an archive, and returns its entries and its offset.
+fn read_directory<R: Read + Seek>(inner: &mut R) -> Result<(Vec<ArchiveEntry>, u64)> {
+    let not_archive = || Error::InvalidFormat("not an archive");
+
+    let mut magic = [0; MAGIC.len()];
+    inner.seek(SeekFrom::Start(0))?;
+    inner.read_exact(&mut magic).map_err(|_| not_archive())?;
+    if magic[..4] != MAGIC[..4] {
+        return Err(not_archive());
+    } else if magic[4] != MAGIC[4] {
+        return Err(Error::InvalidFormat("unsupported archive version"));
+    }
+
+    let len = inner.seek(SeekFrom::End(0))?;
+    if len < (MAGIC.len() + FOOTER_SIZE) as u64 {
+        return Err(not_archive());
+    }
+
+    let mut footer = [0; FOOTER_SIZE];
+    inner.seek(SeekFrom::Start(len - FOOTER_SIZE as u64))?;
+    inner.read_exact(&mut footer)?;
+    if footer[24..] != MAGIC[..4] {
+        return Err(Error::InvalidFormat("archive without directory"));
+    }
+
+    let offset = be_u64(&footer[..8]);
+    let size = be_u64(&footer[8..16]);
+    let count = be_u32(&footer[16..20]) as usize;
+    if offset < MAGIC.len() as u64 || offset.checked_add(size) != Some(len - FOOTER_SIZE as u64) {
+        return Err(Error::InvalidFormat("invalid archive directory"));
+    }
+
+    let mut directory = vec![0; size as usize];
+    inner.seek(SeekFrom::Start(offset))?;
+    inner.read_exact(&mut directory)?;
+    if crc32(0, &directory) != be_u32(&footer[20..24]) {
+        return Err(Error::InvalidFormat("damaged archive directory"));
+    }
+
+    let mut entries = Vec::with_capacity(count.min(directory.len() / RECORD_SIZE));
+    let mut pos = 0;
+    for _ in 0..count {
+        let (entry, size) = ArchiveEntry::read(&directory[pos..])?;
+        let end = entry.offset.checked_add(entry.compressed_size);
+        let in_block = match entry.solid {
+            Some(solid) => matches!(
+                solid.offset.checked_add(entry.uncompressed_size),
+                Some(end) if end <= solid.block_size && solid.block_size <= usize::MAX as u64
+            ),
+            None => true,
+        };
+        if entry.offset < MAGIC.len() as u64
+            || !matches!(end, Some(end) if end <= offset)
+            || !in_block
+        {
+            return Err(Error::InvalidFormat("invalid archive directory"));
+        }
+
+        entries.push(entry);
+        pos += size;
+    }
+
+    Ok((entries, offset))
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes(bytes.try_into().unwrap())
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    u64::from_be_bytes(bytes.try_into().unwrap())
+}
@@ -0,0 +1,288 @@
+//! A benchmark comparing compression options on a directory of files.
+//!
+//! Every combination of the given levels, dictionary sizes, table update rates and helper
+//! threads compresses and decompresses all the files, and the ratio, the speeds and the peak
+//! memory of the native codec are printed as a table or as CSV.
+//!
+//! It requires the `cli` feature.
+
+// The `cli` feature needs the Rust version of clap, above the minimum of the library.
+#![allow(clippy::incompatible_msrv)]
+
+#[path = "../lzham/level.rs"]
+mod level;
+
+use clap::{Parser, ValueEnum};
+use level::Level;
+use lzham::{
+    compress::Compressor, decompress::Decompressor, memory, CompressionOptions,
+    DecompressionOptions, TableUpdateRate,
+};
+use std::{
+    error::Error,
+    fs, io,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::{Duration, Instant},
+};
+
+/// Compares the compression options of LZHAM on local files.
+///
+/// Lists of values are separated by commas, and every combination is measured.
+#[derive(Debug, Parser)]
+#[command(name = "lzham-bench", version)]
+struct Args {
+    /// The file or directory of files to compress. Directories are read recursively.
+    path: PathBuf,
+    /// The levels of compression.
+    #[arg(
+        short = 'L',
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "default"
+    )]
+    levels: Vec<Level>,
+    /// The base 2 logs of the dictionary size.
+    #[arg(long, value_name = "LOG2", value_delimiter = ',', default_value = "26",
+        value_parser = clap::value_parser!(u32).range(15..=29))]
+    dict_sizes: Vec<u32>,
+    /// The table update rates, from 1 (slowest) to 20 (fastest).
+    #[arg(long, value_name = "RATE", value_delimiter = ',', default_value = "8",
+        value_parser = clap::value_parser!(u32).range(1..=20))]
+    table_update_rates: Vec<u32>,
+    /// The maximum numbers of helper threads used to compress.
+    #[arg(short = 'T', long, value_name = "N", value_delimiter = ',', default_value = "0",
+        value_parser = clap::value_parser!(i32).range(0..=64))]
+    threads: Vec<i32>,
+    /// The number of times every combination runs. The fastest run is kept.
+    #[arg(short = 'n', long, default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..))]
+    iterations: u32,
+    /// The output format.
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    Table,
+    Csv,
+}
+
+/// The measures of a combination of options over all the files.
+#[derive(Debug, Default)]
+struct Measure {
+    uncompressed: u64,
+    compressed: u64,
+    compress_time: Duration,
+    decompress_time: Duration,
+    compress_peak: usize,
+    decompress_peak: usize,
+}
+
+impl Measure {
+    /// Returns the compressed size divided by the uncompressed size, like the reports of the
+    /// crate.
+    fn ratio(&self) -> f64 {
+        if self.uncompressed == 0 {
+            0.0
+        } else {
+            self.compressed as f64 / self.uncompressed as f64
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mut files = Vec::new();
+    if let Err(e) = read_files(&args.path, &mut files) {
+        eprintln!("lzham-bench: {}: {}", args.path.display(), e);
+        return ExitCode::FAILURE;
+    }
+    if files.is_empty() {
+        eprintln!("lzham-bench: {}: no files", args.path.display());
+        return ExitCode::FAILURE;
+    }
+
+    let total: usize = files.iter().map(Vec::len).sum();
+    eprintln!("{} files, {} bytes", files.len(), total);
+
+    print_header(args.format);
+
+    for &level in &args.levels {
+        for &dict_size_log2 in &args.dict_sizes {
+            for &rate in &args.table_update_rates {
+                for &threads in &args.threads {
+                    let options = CompressionOptions {
+                        dict_size_log2,
+                        compression_level: level.into(),
+                        table_update_rate: TableUpdateRate::new(rate),
+                        max_helper_threads: threads,
+                        ..Default::default()
+                    };
+
+                    match run(&files, &options, args.iterations) {
+                        Ok(measure) => print_row(args.format, level, rate, &options, &measure),
+                        Err(e) => {
+                            eprintln!(
+                                "lzham-bench: {:?}, dictionary 2^{}, rate {}, {} threads: {}",
+                                level, dict_size_log2, rate, threads, e
+                            );
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// Reads the file at `path`, or all the files in the directory recursively.
+fn read_files(path: &Path, files: &mut Vec<Vec<u8>>) -> io::Result<()> {
+    if !path.is_dir() {
+        files.push(fs::read(path)?);
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+
+    for entry in entries {
+        read_files(&entry, files)?;
+    }
+
+    Ok(())
+}
+
+/// Compresses and decompresses every file with `options`, and checks the roundtrip.
+fn run(
+    files: &[Vec<u8>],
+    options: &CompressionOptions,
+    iterations: u32,
+) -> Result<Measure, Box<dyn Error>> {
+    let decompress_options = DecompressionOptions {
+        dict_size_log2: options.dict_size_log2,
+        table_update_rate: options.table_update_rate,
+        ..Default::default()
+    };
+
+    let mut measure = Measure::default();
+
+    for data in files {
+        let mut comp = Vec::new();
+        let mut decomp = Vec::with_capacity(data.len());
+        let mut compress_time = Duration::MAX;
+        let mut decompress_time = Duration::MAX;
+        let mut sizes = (0, 0);
+
+        for _ in 0..iterations {
+            comp.clear();
+            memory::reset_peak();
+            let start = Instant::now();
+            let report = Compressor::from_options(options.clone())
+                .compress_to_vec_with_report(data, &mut comp)?;
+            compress_time = compress_time.min(start.elapsed());
+            sizes = (report.bytes_in, report.bytes_out);
+            measure.compress_peak = measure.compress_peak.max(memory::stats().peak_bytes);
+
+            decomp.clear();
+            memory::reset_peak();
+            let start = Instant::now();
+            Decompressor::from_options(decompress_options.clone()).decompress_to_vec_with_report(
+                &comp,
+                &mut decomp,
+                data.len(),
+            )?;
+            decompress_time = decompress_time.min(start.elapsed());
+            measure.decompress_peak = measure.decompress_peak.max(memory::stats().peak_bytes);
+
+            if decomp != *data {
+                return Err("the decompressed data differs".into());
+            }
+        }
+
+        measure.uncompressed += sizes.0;
+        measure.compressed += sizes.1;
+        measure.compress_time += compress_time;
+        measure.decompress_time += decompress_time;
+    }
+
+    Ok(measure)
+}
+
+fn print_header(format: Format) {
+    match format {
+        Format::Table => println!(
+            "{:<8} {:>4} {:>4} {:>7} {:>7} {:>10} {:>10} {:>9} {:>9}",
+            "level",
+            "dict",
+            "rate",
+            "threads",
+            "ratio",
+            "comp MB/s",
+            "dec MB/s",
+            "comp MiB",
+            "dec MiB"
+        ),
+        Format::Csv => println!(
+            "level,dict_size_log2,table_update_rate,max_helper_threads,uncompressed,compressed,\
+             ratio,compress_mb_s,decompress_mb_s,compress_peak_bytes,decompress_peak_bytes"
+        ),
+    }
+}
+
+fn print_row(
+    format: Format,
+    level: Level,
+    rate: u32,
+    options: &CompressionOptions,
+    measure: &Measure,
+) {
+    let level = format!("{:?}", level).to_lowercase();
+    let compress_speed = speed(measure.uncompressed, measure.compress_time);
+    let decompress_speed = speed(measure.uncompressed, measure.decompress_time);
+
+    match format {
+        Format::Table => println!(
+            "{:<8} {:>4} {:>4} {:>7} {:>7.3} {:>10.2} {:>10.2} {:>9.1} {:>9.1}",
+            level,
+            options.dict_size_log2,
+            rate,
+            options.max_helper_threads,
+            measure.ratio(),
+            compress_speed,
+            decompress_speed,
+            mebibytes(measure.compress_peak),
+            mebibytes(measure.decompress_peak),
+        ),
+        Format::Csv => println!(
+            "{},{},{},{},{},{},{:.4},{:.3},{:.3},{},{}",
+            level,
+            options.dict_size_log2,
+            rate,
+            options.max_helper_threads,
+            measure.uncompressed,
+            measure.compressed,
+            measure.ratio(),
+            compress_speed,
+            decompress_speed,
+            measure.compress_peak,
+            measure.decompress_peak,
+        ),
+    }
+}
+
+/// Returns the speed in megabytes per second.
+fn speed(bytes: u64, time: Duration) -> f64 {
+    bytes as f64 / 1e6 / time.as_secs_f64().max(1e-9)
+}
+
+fn mebibytes(bytes: usize) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
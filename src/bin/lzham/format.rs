@@ -0,0 +1,388 @@
+//! The file format of the command-line tool.
+//!
+//! A raw LZHAM stream does not say how it was compressed, so files start with a 12 bytes
+//! header: the magic bytes `LZHM`, the format version, the base 2 log of the dictionary
+//! size, the table update rate, a reserved byte, and the Adler-32 of the seed dictionary, or
+//! zero without one. The LZHAM stream follows, and then the size of the uncompressed data.
+//! Integers are big-endian.
+
+use lzham::{
+    compress::{CompressionLevel, Compressor},
+    decompress::{DecompressionFlag, Decompressor},
+    CompressionOptions, DecompressionOptions, TableUpdateRate,
+};
+use std::{
+    convert::TryFrom,
+    io::{self, BufRead, Read, Seek, SeekFrom, Write},
+};
+
+const MAGIC: [u8; 4] = *b"LZHM";
+const VERSION: u8 = 1;
+
+pub const HEADER_SIZE: u64 = 12;
+pub const TRAILER_SIZE: u64 = 8;
+
+/// The parameters of a compression.
+#[derive(Clone, Debug)]
+pub struct Params {
+    pub level: CompressionLevel,
+    pub dict_size_log2: u32,
+    pub table_update_rate: u32,
+    pub max_helper_threads: i32,
+}
+
+/// The sizes of a compressed file.
+#[derive(Clone, Copy, Debug)]
+pub struct Sizes {
+    pub compressed: u64,
+    pub uncompressed: u64,
+}
+
+impl Sizes {
+    /// Returns the space saved by the compression, as a percentage.
+    pub fn saved(&self) -> f64 {
+        if self.uncompressed == 0 {
+            0.0
+        } else {
+            100.0 - self.compressed as f64 * 100.0 / self.uncompressed as f64
+        }
+    }
+}
+
+struct Header {
+    dict_size_log2: u32,
+    table_update_rate: u32,
+    seed_adler32: u32,
+}
+
+impl Header {
+    fn write<W: Write>(&self, output: &mut W) -> io::Result<()> {
+        let mut header = [0; HEADER_SIZE as usize];
+        header[..4].copy_from_slice(&MAGIC);
+        header[4] = VERSION;
+        header[5] = self.dict_size_log2 as u8;
+        header[6] = self.table_update_rate as u8;
+        header[8..].copy_from_slice(&self.seed_adler32.to_be_bytes());
+
+        output.write_all(&header)
+    }
+
+    fn read<R: Read>(input: &mut R) -> io::Result<Self> {
+        let mut header = [0; HEADER_SIZE as usize];
+        input.read_exact(&mut header).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => invalid_data("not in lzham format"),
+            _ => e,
+        })?;
+
+        if header[..4] != MAGIC {
+            return Err(invalid_data("not in lzham format"));
+        } else if header[4] != VERSION {
+            return Err(invalid_data(format!(
+                "unsupported format version {}",
+                header[4]
+            )));
+        }
+
+        Ok(Self {
+            dict_size_log2: header[5] as u32,
+            table_update_rate: header[6] as u32,
+            seed_adler32: u32::from_be_bytes([header[8], header[9], header[10], header[11]]),
+        })
+    }
+}
+
+/// Compresses `input` into `output`, with the header and the trailer.
+pub fn compress<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    params: &Params,
+    seed: Option<&[u8]>,
+) -> io::Result<Sizes> {
+    Header {
+        dict_size_log2: params.dict_size_log2,
+        table_update_rate: params.table_update_rate,
+        seed_adler32: seed.map_or(0, adler32),
+    }
+    .write(output)?;
+
+    let mut compressor = Compressor::from_options(CompressionOptions {
+        dict_size_log2: params.dict_size_log2,
+        compression_level: params.level,
+        table_update_rate: TableUpdateRate::new(params.table_update_rate),
+        max_helper_threads: params.max_helper_threads,
+        p_seed_bytes: seed.map(<[u8]>::to_vec),
+        ..Default::default()
+    });
+    let report = compressor.compress_with_report(input, output)?;
+
+    output.write_all(&report.bytes_in.to_be_bytes())?;
+
+    Ok(Sizes {
+        compressed: HEADER_SIZE + report.bytes_out + TRAILER_SIZE,
+        uncompressed: report.bytes_in,
+    })
+}
+
+/// Decompresses `input` into `output`, and checks the checksum and the size of the data.
+///
+/// If the decompressed data is larger than `limit`, it fails.
+pub fn decompress<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    seed: Option<&[u8]>,
+    limit: u64,
+) -> io::Result<Sizes> {
+    let header = Header::read(input)?;
+
+    match (header.seed_adler32, seed) {
+        (0, None) => {}
+        (_, None) => {
+            return Err(invalid_input(
+                "compressed with a seed dictionary, use --dictionary",
+            ))
+        }
+        (expected, Some(seed)) if adler32(seed) != expected => {
+            return Err(invalid_input("the seed dictionary does not match"))
+        }
+        _ => {}
+    }
+
+    let mut decompressor = Decompressor::from_options(DecompressionOptions {
+        dict_size_log2: header.dict_size_log2,
+        table_update_rate: TableUpdateRate::new(header.table_update_rate),
+        decompression_flags: Some(DecompressionFlag::ComputeAdler32),
+        p_seed_bytes: seed.map(<[u8]>::to_vec),
+        ..Default::default()
+    });
+    let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+    // The trailer follows the stream, so the end of the input means it is truncated.
+    let report = decompressor.decompress_with_report(&mut NoEof(&mut *input), output, limit)?;
+
+    let mut trailer = [0; TRAILER_SIZE as usize];
+    input.read_exact(&mut trailer)?;
+
+    if u64::from_be_bytes(trailer) != report.bytes_out {
+        return Err(invalid_data("size mismatch"));
+    } else if !input.fill_buf()?.is_empty() {
+        return Err(invalid_data("trailing data after the compressed stream"));
+    }
+
+    Ok(Sizes {
+        compressed: HEADER_SIZE + report.bytes_in + TRAILER_SIZE,
+        uncompressed: report.bytes_out,
+    })
+}
+
+/// Reads the sizes of a compressed file without decompressing it.
+///
+/// The file is left at its start.
+pub fn read_sizes<F: Read + Seek>(file: &mut F) -> io::Result<Sizes> {
+    Header::read(file)?;
+
+    let compressed = file.seek(SeekFrom::End(0))?;
+    if compressed < HEADER_SIZE + TRAILER_SIZE {
+        return Err(invalid_data("unexpected end of file"));
+    }
+
+    let mut trailer = [0; TRAILER_SIZE as usize];
+    file.seek(SeekFrom::End(-(TRAILER_SIZE as i64)))?;
+    file.read_exact(&mut trailer)?;
+    file.seek(SeekFrom::Start(0))?;
+
+    Ok(Sizes {
+        compressed,
+        uncompressed: u64::from_be_bytes(trailer),
+    })
+}
+
+/// An input whose end is an error, because a truncated stream would otherwise be decoded as
+/// if it was padded with zeros.
+struct NoEof<R>(R);
+
+impl<R: BufRead> Read for NoEof<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.len().min(buf.len());
+        buf[..n].copy_from_slice(&self.0.fill_buf()?[..n]);
+        self.consume(n);
+
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for NoEof<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let buf = self.0.fill_buf()?;
+        if buf.is_empty() {
+            Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "unexpected end of file",
+            ))
+        } else {
+            Ok(buf)
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    unsafe {
+        lzham_sys::lzham_z_adler32(
+            lzham_sys::LZHAM_Z_ADLER32_INIT as lzham_sys::lzham_z_ulong,
+            data.as_ptr(),
+            data.len() as lzham_sys::size_t,
+        ) as u32
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+fn invalid_input<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, e)
+}
+
+mod test {
+    #[cfg(test)]
+    fn params() -> super::Params {
+        super::Params {
+            level: lzham::compress::CompressionLevel::Default,
+            dict_size_log2: 20,
+            table_update_rate: 8,
+            max_helper_threads: 0,
+        }
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        use super::{compress, decompress, HEADER_SIZE, TRAILER_SIZE};
+
+        let data = b"This is a test.This is a test.1234567This is a test.".repeat(100);
+
+        let mut comp = Vec::new();
+        let sizes = compress(&mut data.as_slice(), &mut comp, &params(), None).unwrap();
+
+        assert_eq!(sizes.compressed, comp.len() as u64);
+        assert_eq!(sizes.uncompressed, data.len() as u64);
+        assert_eq!(&comp[..4], b"LZHM");
+        assert_eq!(
+            comp[comp.len() - TRAILER_SIZE as usize..],
+            (data.len() as u64).to_be_bytes()
+        );
+        assert!(comp.len() as u64 > HEADER_SIZE + TRAILER_SIZE);
+
+        let mut decomp = Vec::new();
+        let sizes = decompress(&mut comp.as_slice(), &mut decomp, None, u64::MAX).unwrap();
+
+        assert_eq!(decomp, data);
+        assert_eq!(sizes.compressed, comp.len() as u64);
+        assert_eq!(sizes.uncompressed, data.len() as u64);
+
+        // The limit is the largest size of the decompressed data.
+        assert!(decompress(&mut comp.as_slice(), &mut Vec::new(), None, 10).is_err());
+    }
+
+    #[test]
+    fn test_truncated_and_trailing_data() {
+        use super::{compress, decompress, HEADER_SIZE, TRAILER_SIZE};
+        use std::io::ErrorKind;
+
+        let data = b"This is a test.This is a test.1234567This is a test.".repeat(100);
+
+        let mut comp = Vec::new();
+        compress(&mut data.as_slice(), &mut comp, &params(), None).unwrap();
+
+        for len in [
+            0,
+            HEADER_SIZE as usize - 1,
+            HEADER_SIZE as usize + 1,
+            comp.len() / 2,
+            comp.len() - TRAILER_SIZE as usize,
+            comp.len() - 1,
+        ] {
+            let error = decompress(&mut &comp[..len], &mut Vec::new(), None, u64::MAX);
+
+            assert!(error.is_err(), "truncated to {} bytes", len);
+        }
+
+        let mut trailing = comp.clone();
+        trailing.push(0);
+        let error =
+            decompress(&mut trailing.as_slice(), &mut Vec::new(), None, u64::MAX).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("trailing data"), "{}", error);
+
+        let mut wrong_size = comp.clone();
+        *wrong_size.last_mut().unwrap() ^= 1;
+        let error =
+            decompress(&mut wrong_size.as_slice(), &mut Vec::new(), None, u64::MAX).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_seed() {
+        use super::{adler32, compress, decompress};
+        use std::io::ErrorKind;
+
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+
+        let seed = b"This is a seed dictionary.".repeat(10);
+        let data = b"This is a test.This is a seed dictionary.".repeat(10);
+
+        let mut comp = Vec::new();
+        compress(&mut data.as_slice(), &mut comp, &params(), Some(&seed)).unwrap();
+
+        assert_eq!(comp[8..12], adler32(&seed).to_be_bytes());
+
+        let mut decomp = Vec::new();
+        decompress(&mut comp.as_slice(), &mut decomp, Some(&seed), u64::MAX).unwrap();
+
+        assert_eq!(decomp, data);
+
+        let error = decompress(&mut comp.as_slice(), &mut Vec::new(), None, u64::MAX).unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("--dictionary"), "{}", error);
+
+        let error = decompress(
+            &mut comp.as_slice(),
+            &mut Vec::new(),
+            Some(b"Another seed"),
+            u64::MAX,
+        )
+        .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    #[test]
+    fn test_read_sizes() {
+        use super::{compress, read_sizes};
+        use std::io::Cursor;
+
+        let data = b"This is a test.".repeat(1000);
+
+        let mut comp = Vec::new();
+        let sizes = compress(&mut data.as_slice(), &mut comp, &params(), None).unwrap();
+
+        let mut file = Cursor::new(&comp);
+        let read = read_sizes(&mut file).unwrap();
+
+        assert_eq!(
+            (read.compressed, read.uncompressed),
+            (sizes.compressed, sizes.uncompressed)
+        );
+        assert_eq!(file.position(), 0);
+        assert!(read.saved() > 90.0);
+
+        assert!(read_sizes(&mut Cursor::new(&comp[..15])).is_err());
+        assert!(read_sizes(&mut Cursor::new(b"This is not lzham.")).is_err());
+    }
+}
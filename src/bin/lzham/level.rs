@@ -0,0 +1,27 @@
+//! The level of compression, as a command-line argument.
+//!
+//! It is shared by the `lzham` and `lzham-bench` binaries.
+
+use clap::ValueEnum;
+use lzham::compress::CompressionLevel;
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Level {
+    Fastest,
+    Faster,
+    Default,
+    Better,
+    Uber,
+}
+
+impl From<Level> for CompressionLevel {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Fastest => Self::Fastest,
+            Level::Faster => Self::Faster,
+            Level::Default => Self::Default,
+            Level::Better => Self::Better,
+            Level::Uber => Self::Uber,
+        }
+    }
+}
@@ -0,0 +1,402 @@
+//! A gzip-like command-line tool to compress and decompress files with LZHAM.
+//!
+//! It requires the `cli` feature.
+
+// The `cli` feature needs the Rust version of clap, above the minimum of the library.
+#![allow(clippy::incompatible_msrv)]
+
+mod format;
+mod level;
+
+use clap::Parser;
+use format::{Params, Sizes};
+use level::Level;
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Cursor, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Compresses or decompresses files with LZHAM.
+///
+/// Files are replaced by their compressed or decompressed version, unless --keep or --stdout
+/// is given. Without files, or with `-`, it reads the standard input and writes the standard
+/// output.
+#[derive(Debug, Parser)]
+#[command(name = "lzham", version)]
+struct Args {
+    /// The files to process.
+    files: Vec<PathBuf>,
+    /// Decompresses the files.
+    #[arg(short, long, group = "mode")]
+    decompress: bool,
+    /// Checks the integrity of compressed files.
+    #[arg(short, long, group = "mode")]
+    test: bool,
+    /// Lists the sizes of compressed files.
+    #[arg(short, long, group = "mode")]
+    list: bool,
+    /// Writes to the standard output and keeps the files.
+    #[arg(short = 'c', long)]
+    stdout: bool,
+    /// Keeps the input files.
+    #[arg(short, long)]
+    keep: bool,
+    /// Overwrites existing output files, and writes compressed data to a terminal.
+    #[arg(short, long)]
+    force: bool,
+    /// Processes the files in directories recursively.
+    #[arg(short, long)]
+    recursive: bool,
+    /// Prints the name and ratio of every file.
+    #[arg(short, long)]
+    verbose: bool,
+    /// The suffix of compressed files.
+    #[arg(short = 'S', long, default_value = ".lzham")]
+    suffix: String,
+    /// The level of compression.
+    #[arg(short = 'L', long, value_enum, default_value_t = Level::Default)]
+    level: Level,
+    /// The base 2 log of the dictionary size.
+    #[arg(long, value_name = "LOG2", default_value_t = 26,
+        value_parser = clap::value_parser!(u32).range(15..=29))]
+    dict_size: u32,
+    /// The table update rate, from 1 (slowest) to 20 (fastest).
+    #[arg(long, value_name = "RATE", default_value_t = 8,
+        value_parser = clap::value_parser!(u32).range(1..=20))]
+    table_update_rate: u32,
+    /// The maximum number of helper threads used to compress.
+    #[arg(short = 'T', long, value_name = "N", default_value_t = 0,
+        value_parser = clap::value_parser!(i32).range(0..=64))]
+    threads: i32,
+    /// A seed dictionary, which must also be given to decompress.
+    #[arg(short = 'D', long, value_name = "FILE")]
+    dictionary: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    Compress,
+    Decompress,
+    Test,
+    List,
+}
+
+struct Cli {
+    args: Args,
+    mode: Mode,
+    params: Params,
+    seed: Option<Vec<u8>>,
+    failed: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let mode = if args.decompress {
+        Mode::Decompress
+    } else if args.test {
+        Mode::Test
+    } else if args.list {
+        Mode::List
+    } else {
+        Mode::Compress
+    };
+
+    let seed = match &args.dictionary {
+        Some(path) => match fs::read(path) {
+            Ok(seed) => Some(seed),
+            Err(e) => {
+                eprintln!("lzham: {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    let params = Params {
+        level: args.level.into(),
+        dict_size_log2: args.dict_size,
+        table_update_rate: args.table_update_rate,
+        max_helper_threads: args.threads,
+    };
+
+    let mut cli = Cli {
+        mode,
+        params,
+        seed,
+        failed: false,
+        args,
+    };
+
+    if cli.mode == Mode::List {
+        println!(
+            "{:>16} {:>16} {:>6}  name",
+            "compressed", "uncompressed", "saved"
+        );
+    }
+
+    let files = std::mem::take(&mut cli.args.files);
+    if files.is_empty() {
+        cli.process(Path::new("-"));
+    }
+    for path in files {
+        cli.process(&path);
+    }
+
+    if cli.failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+impl Cli {
+    fn process(&mut self, path: &Path) {
+        let result = if path == Path::new("-") {
+            self.process_stdin()
+        } else if path.is_dir() {
+            self.process_dir(path)
+        } else {
+            self.process_file(path)
+        };
+
+        if let Err(e) = result {
+            let name = if path == Path::new("-") {
+                "stdin".into()
+            } else {
+                path.display().to_string()
+            };
+            eprintln!("lzham: {}: {}", name, e);
+            self.failed = true;
+        }
+    }
+
+    fn process_dir(&mut self, path: &Path) -> io::Result<()> {
+        if !self.args.recursive {
+            eprintln!("lzham: {} is a directory -- ignored", path.display());
+            return Ok(());
+        }
+
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            // Only compressed files are decompressed, tested or listed in directories.
+            if entry.is_dir() || (self.mode == Mode::Compress) != self.has_suffix(&entry) {
+                self.process(&entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_stdin(&mut self) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut input = stdin.lock();
+
+        match self.mode {
+            Mode::Compress => {
+                if io::stdout().is_terminal() && !self.args.force {
+                    return Err(io::Error::other(
+                        "compressed data not written to a terminal, use --force to force it",
+                    ));
+                }
+
+                let sizes = self.compress(&mut input, &mut io::stdout().lock())?;
+                self.report("stdin", sizes);
+            }
+            Mode::Decompress | Mode::Test => {
+                if stdin.is_terminal() && !self.args.force {
+                    return Err(io::Error::other(
+                        "compressed data not read from a terminal, use --force to force it",
+                    ));
+                }
+
+                let sizes = if self.mode == Mode::Test {
+                    self.decompress(&mut input, &mut io::sink(), u64::MAX)?
+                } else {
+                    self.decompress(&mut input, &mut io::stdout().lock(), u64::MAX)?
+                };
+                self.report("stdin", sizes);
+            }
+            Mode::List => {
+                let mut data = Vec::new();
+                input.read_to_end(&mut data)?;
+
+                let sizes = format::read_sizes(&mut Cursor::new(data))?;
+                self.report("stdin", sizes);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn process_file(&mut self, path: &Path) -> io::Result<()> {
+        let name = path.display().to_string();
+
+        match self.mode {
+            Mode::Compress if self.has_suffix(path) => {
+                eprintln!(
+                    "lzham: {} already has {} suffix -- unchanged",
+                    name, self.args.suffix
+                );
+                Ok(())
+            }
+            Mode::Decompress if !self.has_suffix(path) && !self.args.stdout => {
+                eprintln!("lzham: {}: unknown suffix -- ignored", name);
+                Ok(())
+            }
+            Mode::Compress | Mode::Decompress => self.convert_file(path),
+            Mode::Test => {
+                let mut file = File::open(path)?;
+                let limit = format::read_sizes(&mut file)?.uncompressed;
+                let sizes = self.decompress(&mut BufReader::new(file), &mut io::sink(), limit)?;
+
+                self.report(&name, sizes);
+                Ok(())
+            }
+            Mode::List => {
+                let sizes = format::read_sizes(&mut File::open(path)?)?;
+
+                self.report(&name, sizes);
+                Ok(())
+            }
+        }
+    }
+
+    /// Compresses or decompresses a file, into a new file or the standard output.
+    fn convert_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        let limit = match self.mode {
+            Mode::Decompress => format::read_sizes(&mut file)?.uncompressed,
+            _ => u64::MAX,
+        };
+        let mut input = BufReader::new(file);
+
+        if self.args.stdout {
+            let sizes = match self.mode {
+                Mode::Decompress => self.decompress(&mut input, &mut io::stdout().lock(), limit)?,
+                _ => self.compress(&mut input, &mut io::stdout().lock())?,
+            };
+
+            self.report(&path.display().to_string(), sizes);
+            return Ok(());
+        }
+
+        let out_path = self.output_path(path);
+        if out_path.exists() && !self.args.force {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists, use --force to overwrite it",
+                    out_path.display()
+                ),
+            ));
+        }
+
+        let mut output = BufWriter::new(File::create(&out_path)?);
+        let result = match self.mode {
+            Mode::Decompress => self.decompress(&mut input, &mut output, limit),
+            _ => self.compress(&mut input, &mut output),
+        }
+        .and_then(|sizes| {
+            output
+                .into_inner()
+                .map_err(|e| e.into_error())?
+                .sync_all()?;
+            Ok(sizes)
+        });
+
+        let sizes = match result {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                // A partial output is useless.
+                let _ = fs::remove_file(&out_path);
+                return Err(e);
+            }
+        };
+
+        fs::set_permissions(&out_path, fs::metadata(path)?.permissions())?;
+        if !self.args.keep {
+            fs::remove_file(path)?;
+        }
+
+        if self.args.verbose {
+            eprintln!(
+                "{}:\t{:5.1}% -- {} {}",
+                path.display(),
+                sizes.saved(),
+                if self.args.keep {
+                    "created"
+                } else {
+                    "replaced with"
+                },
+                out_path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn compress<R: BufRead, W: Write>(&self, input: &mut R, output: &mut W) -> io::Result<Sizes> {
+        let sizes = format::compress(input, output, &self.params, self.seed.as_deref())?;
+        output.flush()?;
+
+        Ok(sizes)
+    }
+
+    fn decompress<R: BufRead, W: Write>(
+        &self,
+        input: &mut R,
+        output: &mut W,
+        limit: u64,
+    ) -> io::Result<Sizes> {
+        let sizes = format::decompress(input, output, self.seed.as_deref(), limit)?;
+        output.flush()?;
+
+        Ok(sizes)
+    }
+
+    /// Prints the result of an operation which writes no file.
+    fn report(&self, name: &str, sizes: Sizes) {
+        match self.mode {
+            Mode::List => println!(
+                "{:>16} {:>16} {:>5.1}%  {}",
+                sizes.compressed,
+                sizes.uncompressed,
+                sizes.saved(),
+                name
+            ),
+            Mode::Test if self.args.verbose => eprintln!("{}:\tOK", name),
+            Mode::Compress | Mode::Decompress if self.args.verbose => {
+                eprintln!("{}:\t{:5.1}%", name, sizes.saved())
+            }
+            _ => {}
+        }
+    }
+
+    fn has_suffix(&self, path: &Path) -> bool {
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| {
+                name.len() > self.args.suffix.len() && name.ends_with(&self.args.suffix)
+            })
+    }
+
+    /// Returns the path of the output file. Decompressed files must have the suffix.
+    fn output_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+
+        if self.mode == Mode::Decompress {
+            let name = name.to_str().unwrap_or_default();
+            path.with_file_name(&name[..name.len() - self.args.suffix.len()])
+        } else {
+            name.push(&self.args.suffix);
+            path.with_file_name(name)
+        }
+    }
+}
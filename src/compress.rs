@@ -3,13 +3,22 @@
 #[doc(inline)]
 pub use super::low::compression::*;
 
+use super::low::{compression::compress_memory, BUF_SIZE, MAX_DICT_SIZE_LOG2, MIN_DICT_SIZE_LOG2};
+use crate::{report::CompressionReport, Error, Result};
 use std::io::{BufRead, Write};
 
+/// The largest size of a block of input data.
+const MAX_BLOCK_SIZE: usize = 512 * 1024;
+/// The worst-case overhead of a raw block, which is 5 bytes in practice.
+const BLOCK_OVERHEAD: usize = 8;
+/// The worst-case overhead of the zlib header, end-of-stream block and checksums.
+const STREAM_OVERHEAD: usize = 64;
+
 /// Compresses input data into the output buffer with default [`options`].
 ///
 /// [`options`]: CompressionOptions
 pub fn compress<R: BufRead, W: Write>(input: &mut R, output: &mut W) -> CompressionStatus {
-    let compressor = Compressor::from_options(CompressionOptions::default());
+    let mut compressor = Compressor::from_options(CompressionOptions::default());
 
     compressor.compress(input, output)
 }
@@ -22,7 +31,191 @@ pub fn compress_with_options<R: BufRead, W: Write>(
     output: &mut W,
     options: CompressionOptions,
 ) -> CompressionStatus {
-    let compressor = Compressor::from_options(options);
+    let mut compressor = Compressor::from_options(options);
 
     compressor.compress(input, output)
 }
+
+/// Compresses `input` into the `output` slice with default [`options`].
+///
+/// Returns the size of the compressed data. See [`compress_into_with_options`] for details.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_into(input: &[u8], output: &mut [u8]) -> Result<usize> {
+    compress_into_with_options(input, output, &CompressionOptions::default())
+}
+
+/// Compresses `input` into the `output` slice with provided [`options`].
+///
+/// Returns the size of the compressed data. It compresses the data in a single call to
+/// the native codec and does not allocate any memory on the Rust side.
+///
+/// If `output` is too small, it returns [`Error::OutputBufferTooSmall`] with the required
+/// size of the output buffer.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_into_with_options(
+    input: &[u8],
+    output: &mut [u8],
+    options: &CompressionOptions,
+) -> Result<usize> {
+    match compress_memory(input, output, options) {
+        (CompressionStatus::Success, len) => Ok(len),
+        (CompressionStatus::OutputBufferTooSmall, len) => {
+            Err(Error::OutputBufferTooSmall(Some(len)))
+        }
+        (status, _) => Err(Error::Compression(status)),
+    }
+}
+
+/// Compresses input data into the output buffer with provided [`options`], and returns a
+/// [`CompressionReport`].
+///
+/// It fails with [`Error::Compression`] if the compression does not succeed.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_with_report<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &CompressionOptions,
+) -> Result<CompressionReport> {
+    let mut compressor = Compressor::from_options(options.clone());
+
+    compressor.compress_with_report(input, output)
+}
+
+/// Compresses `input` into a new vector with provided [`options`].
+///
+/// The data is compressed directly into the returned vector.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_to_vec(input: &[u8], options: &CompressionOptions) -> Result<Vec<u8>> {
+    compress_to_vec_with_report(input, options).map(|(output, _)| output)
+}
+
+/// Compresses `input` into a new vector with provided [`options`], and returns it along
+/// with a [`CompressionReport`].
+///
+/// [`options`]: CompressionOptions
+pub fn compress_to_vec_with_report(
+    input: &[u8],
+    options: &CompressionOptions,
+) -> Result<(Vec<u8>, CompressionReport)> {
+    let mut compressor = Compressor::from_options(options.clone());
+    let mut output = Vec::with_capacity(input.len() / 2 + 64);
+    let report = compressor.compress_to_vec_with_report(input, &mut output)?;
+
+    Ok((output, report))
+}
+
+/// Returns the largest possible size of `len` bytes compressed with provided [`options`].
+///
+/// The native codec splits the input into blocks of an eighth of the dictionary size (at
+/// most 512 KiB), and stores every block which does not compress as a raw block with a
+/// small header. The bound accounts for one such header per block, plus the stream header
+/// and trailer. It does not account for explicit flushes.
+///
+/// [`compress_into`] and [`compress_into_with_options`] always succeed with an output
+/// buffer of this size.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_bound(len: usize, options: &CompressionOptions) -> usize {
+    let blocks = len / block_size(options.dict_size_log2) + 1;
+
+    len.saturating_add(blocks.saturating_mul(BLOCK_OVERHEAD))
+        .saturating_add(STREAM_OVERHEAD)
+}
+
+/// Returns the largest size of a block of input data with the given dictionary size.
+pub(crate) fn block_size(dict_size_log2: u32) -> usize {
+    let dict_size_log2 = dict_size_log2.clamp(MIN_DICT_SIZE_LOG2, MAX_DICT_SIZE_LOG2);
+    ((1 << dict_size_log2) / 8).min(MAX_BLOCK_SIZE)
+}
+
+/// Compresses data incrementally through an intermediate output buffer.
+///
+/// It is shared by the streaming encoders, which pass the [`output`] of every call to their
+/// sink. It applies flushes the way the native codec expects them.
+///
+/// [`output`]: Encoder::output
+#[derive(Debug)]
+pub(crate) struct Encoder {
+    compressor: Compressor,
+    buf: Box<[u8]>,
+    len: usize,
+    // Whether the codec has more output than it returned by the last call.
+    pending: bool,
+    // Whether the flush in progress was passed to the codec.
+    applied: bool,
+}
+
+impl Encoder {
+    pub(crate) fn new(options: CompressionOptions) -> Self {
+        Self {
+            compressor: Compressor::from_options(options),
+            buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            len: 0,
+            pending: false,
+            applied: false,
+        }
+    }
+
+    /// Returns the output of the last call to the codec.
+    pub(crate) fn output(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Passes `input` to the codec once, and returns the number of bytes it read.
+    ///
+    /// The codec may return output without reading anything, so it must be called again
+    /// until it reads some of a non-empty `input`.
+    pub(crate) fn compress(&mut self, input: &[u8]) -> Result<usize> {
+        let (status, read) = self.call(input, CompressionFlush::None);
+
+        if status.is_success_or_first_failure() {
+            Err(Error::Compression(status))
+        } else {
+            Ok(read)
+        }
+    }
+
+    /// Passes `flush` to the codec once, and returns whether the flush is complete.
+    ///
+    /// It must be called again with the same flush until it is complete. The stream is
+    /// finished once a [`Finish`] flush is complete.
+    ///
+    /// [`Finish`]: CompressionFlush::Finish
+    pub(crate) fn flush(&mut self, flush: CompressionFlush) -> Result<bool> {
+        // The codec ignores the flush while it has pending output, and a sync flush is
+        // applied once, the remaining output is drained without it.
+        let current = if self.pending || (self.applied && flush == CompressionFlush::Sync) {
+            CompressionFlush::None
+        } else {
+            flush
+        };
+
+        let (status, _) = self.call(&[], current);
+        self.applied |= current == flush;
+
+        let complete = match status {
+            CompressionStatus::Success => true,
+            status if status.is_success_or_first_failure() => {
+                return Err(Error::Compression(status))
+            }
+            _ => self.applied && !self.pending && flush == CompressionFlush::Sync,
+        };
+        if complete {
+            self.applied = false;
+        }
+
+        Ok(complete)
+    }
+
+    fn call(&mut self, input: &[u8], flush: CompressionFlush) -> (CompressionStatus, usize) {
+        let (status, read, written) = self.compressor.compress_buf(input, &mut self.buf, flush);
+        self.len = written;
+        self.pending = matches!(status, CompressionStatus::HasMoreOutput);
+
+        (status, read)
+    }
+}
@@ -3,23 +3,44 @@
 #[doc(inline)]
 pub use super::low::decompression::*;
 
-use std::io::{BufRead, Write};
+use super::low::decompression::decompress_memory;
+use crate::{report::DecompressionReport, Error, Result};
+use std::io::{self, BufRead, Read, Write};
+
+/// The expected size of the decompressed data, passed to [`decompress_to_vec`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecompressedSize {
+    /// The decompressed data has exactly this size.
+    Exact(usize),
+    /// The decompressed data has at most this size.
+    Limit(usize),
+    /// The size of the decompressed data is not known.
+    Unknown,
+}
 
 /// Decompresses input data into the output buffer with default [`options`].
 ///
+/// Only the bytes of the stream are consumed, so any data following it is left in `input`.
+/// The status does not hold the size of the stream: it is the number of bytes `input`
+/// advanced by, or use [`decompress_with_report`] to get it.
+///
 /// [`options`]: DecompressionOptions
 pub fn decompress<R: BufRead, W: Write>(
     input: &mut R,
     output: &mut W,
     uncompressed_size: usize,
 ) -> DecompressionStatus {
-    let decompressor = Decompressor::from_options(DecompressionOptions::default());
+    let mut decompressor = Decompressor::from_options(DecompressionOptions::default());
 
     decompressor.decompress(input, output, uncompressed_size)
 }
 
 /// Decompresses input data into the output buffer with provided [`options`].
 ///
+/// Only the bytes of the stream are consumed, so any data following it is left in `input`.
+/// The status does not hold the size of the stream: it is the number of bytes `input`
+/// advanced by, or use [`decompress_with_report`] to get it.
+///
 /// [`options`]: DecompressionOptions
 pub fn decompress_with_options<R: BufRead, W: Write>(
     input: &mut R,
@@ -27,7 +48,189 @@ pub fn decompress_with_options<R: BufRead, W: Write>(
     uncompressed_size: usize,
     options: DecompressionOptions,
 ) -> DecompressionStatus {
-    let decompressor = Decompressor::from_options(options);
+    let mut decompressor = Decompressor::from_options(options);
 
     decompressor.decompress(input, output, uncompressed_size)
 }
+
+/// Decompresses `input` into the `output` slice with default [`options`].
+///
+/// Returns the size of the decompressed data. See [`decompress_into_with_options`] for
+/// details.
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_into(input: &[u8], output: &mut [u8]) -> Result<usize> {
+    decompress_into_with_options(input, output, &DecompressionOptions::default())
+}
+
+/// Decompresses `input` into the `output` slice with provided [`options`].
+///
+/// Returns the size of the decompressed data. It decompresses the data in a single call to
+/// the native codec and does not allocate any memory on the Rust side.
+///
+/// If `output` is too small, it returns [`Error::OutputBufferTooSmall`]. The required size
+/// is not known, as it is not stored in the compressed data. Note that the native codec
+/// reports [`DecompressionStatus::BadCode`] instead if the output overflows in the middle
+/// of a match.
+///
+/// Any data following the stream in `input` is ignored. The single call to the native codec
+/// does not report how much of `input` it read, so the size of the stream is not known. Use
+/// [`decompress_into_with_report`] to get it.
+///
+/// The native codec does not support seed bytes in this mode, so it fails with
+/// [`DecompressionStatus::InvalidParameter`] if the options have any. Use [`Decompressor`]
+/// instead.
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_into_with_options(
+    input: &[u8],
+    output: &mut [u8],
+    options: &DecompressionOptions,
+) -> Result<usize> {
+    match decompress_memory(input, output, options) {
+        (DecompressionStatus::Success, len) => Ok(len),
+        (DecompressionStatus::OutputBufferTooSmall, _) => Err(Error::OutputBufferTooSmall(None)),
+        (status, _) => Err(Error::Decompression(status)),
+    }
+}
+
+/// Decompresses `input` into the `output` slice with provided [`options`], and returns a
+/// [`DecompressionReport`].
+///
+/// The size of the decompressed data and of the stream are given by the report. Any data
+/// following the stream in `input` is ignored. If `output` is too small, it returns
+/// [`Error::OutputBufferTooSmall`].
+///
+/// Unlike [`decompress_into_with_options`], it decompresses the data incrementally, so
+/// seed bytes are supported.
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_into_with_report(
+    input: &[u8],
+    output: &mut [u8],
+    options: &DecompressionOptions,
+) -> Result<DecompressionReport> {
+    let mut decompressor = Decompressor::from_options(options.clone());
+
+    decompressor.decompress_into_with_report(input, output)
+}
+
+/// Decompresses input data into the output buffer with provided [`options`], and returns
+/// a [`DecompressionReport`].
+///
+/// If the decompressed data is larger than `uncompressed_size`, it returns
+/// [`Error::OutputBufferTooSmall`].
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_with_report<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    uncompressed_size: usize,
+    options: &DecompressionOptions,
+) -> Result<DecompressionReport> {
+    let mut decompressor = Decompressor::from_options(options.clone());
+
+    decompressor.decompress_with_report(input, output, uncompressed_size)
+}
+
+/// Decompresses `input` into a new vector with provided [`options`].
+///
+/// The data is decompressed directly into the returned vector, which is preallocated based
+/// on `size`. If the decompressed data is larger than the exact size or the limit, it returns
+/// [`Error::OutputBufferTooSmall`]. If it is smaller than the exact size, it returns
+/// [`Error::SizeMismatch`].
+///
+/// Any data following the stream in `input` is ignored. Only the decompressed data is
+/// returned, so use [`decompress_to_vec_with_report`] to get the size of the stream.
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_to_vec(
+    input: &[u8],
+    options: &DecompressionOptions,
+    size: DecompressedSize,
+) -> Result<Vec<u8>> {
+    decompress_to_vec_with_report(input, options, size).map(|(output, _)| output)
+}
+
+/// Decompresses `input` into a new vector with provided [`options`], and returns it along
+/// with a [`DecompressionReport`].
+///
+/// See [`decompress_to_vec`] for details.
+///
+/// [`options`]: DecompressionOptions
+pub fn decompress_to_vec_with_report(
+    input: &[u8],
+    options: &DecompressionOptions,
+    size: DecompressedSize,
+) -> Result<(Vec<u8>, DecompressionReport)> {
+    let expected = input.len().saturating_mul(4);
+    let (capacity, limit) = match size {
+        DecompressedSize::Exact(size) => (size, size),
+        DecompressedSize::Limit(limit) => (expected.min(limit), limit),
+        DecompressedSize::Unknown => (expected, usize::MAX),
+    };
+
+    let mut decompressor = Decompressor::from_options(options.clone());
+    let mut output = Vec::with_capacity(capacity);
+    let report = decompressor.decompress_to_vec_with_report(input, &mut output, limit)?;
+
+    match size {
+        DecompressedSize::Exact(size) if size != output.len() => Err(Error::SizeMismatch {
+            expected: size,
+            actual: output.len(),
+        }),
+        _ => Ok((output, report)),
+    }
+}
+
+/// Decompresses the data read from a [`BufRead`].
+#[derive(Debug)]
+pub(crate) struct Decoder<R: BufRead> {
+    inner: R,
+    decompressor: Decompressor,
+    // Whether the last call wrote output, and the codec may have more without input.
+    pending: bool,
+    done: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub(crate) fn new(inner: R, options: DecompressionOptions) -> Self {
+        Self {
+            inner,
+            decompressor: Decompressor::from_options(options),
+            pending: false,
+            done: false,
+        }
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // The end of the input is never signaled to the codec, which would pad it with
+            // zeros and may then decompress invalid data endlessly. A complete stream ends
+            // without it.
+            let input = self.inner.fill_buf()?;
+            if input.is_empty() && !self.pending {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+
+            let (status, read, written) = self.decompressor.decompress_buf(input, buf, false);
+            self.inner.consume(read);
+            self.pending = written > 0;
+
+            if status.is_success() {
+                self.done = true;
+                return Ok(written);
+            } else if status.is_success_or_first_failure() {
+                return Err(Error::Decompression(status).into());
+            } else if written > 0 {
+                return Ok(written);
+            }
+        }
+    }
+}
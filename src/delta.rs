@@ -0,0 +1,222 @@
+//! Binary delta encoding, with the old data as a seed dictionary.
+//!
+//! [`diff`] compresses the new data with the old data loaded as [seed bytes], so the parts
+//! they share are encoded as matches into the old data. When the two are similar, like two
+//! builds of the same program, the patch is much smaller than the new data compressed on
+//! its own. [`patch`] rebuilds the new data from the old data and the patch.
+//!
+//! ## Format
+//!
+//! A patch starts with a 32 bytes header, followed by the LZHAM stream:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 4    | The magic bytes `LZHD`                  |
+//! | 4      | 1    | The format version, `1`                 |
+//! | 5      | 1    | The base 2 log of the dictionary size   |
+//! | 6      | 2    | Reserved, zero                          |
+//! | 8      | 8    | The size of the old data                |
+//! | 16     | 4    | The CRC-32 of the old data              |
+//! | 20     | 8    | The size of the new data                |
+//! | 28     | 4    | The CRC-32 of the new data              |
+//!
+//! Integers are big-endian. The dictionary is large enough to hold both the old and the new
+//! data, up to 512 MiB. If the old data is larger than the dictionary, only its end is used
+//! as the seed.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::delta::{diff, patch};
+//! let old = b"This is a test. This is the old version.".repeat(100);
+//! let new = b"This is a test. This is the new version.".repeat(100);
+//!
+//! let delta = diff(&old, &new).unwrap();
+//!
+//! assert_eq!(patch(&old, &delta).unwrap(), new);
+//! ```
+//!
+//! [seed bytes]: CompressionOptions::p_seed_bytes
+
+use crate::{
+    compress::Compressor,
+    decompress::{DecompressionFlag, Decompressor},
+    low::{crc32, seed_tail, MAX_DICT_SIZE_LOG2, MIN_DICT_SIZE_LOG2},
+    CompressionOptions, DecompressionOptions, Error, Result,
+};
+use std::convert::{TryFrom, TryInto};
+
+/// The bytes starting every patch.
+pub(crate) const MAGIC: [u8; 5] = [b'L', b'Z', b'H', b'D', 1];
+
+/// The size of the patch header.
+const HEADER_SIZE: usize = 32;
+
+/// The header of a patch.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PatchHeader {
+    /// The base 2 log of the dictionary size.
+    pub dict_size_log2: u32,
+    /// The size of the old data.
+    pub base_size: u64,
+    /// The CRC-32 of the old data.
+    pub base_crc32: u32,
+    /// The size of the new data.
+    pub target_size: u64,
+    /// The CRC-32 of the new data.
+    pub target_crc32: u32,
+}
+
+impl PatchHeader {
+    /// Reads the header at the start of `patch`.
+    ///
+    /// It fails with [`Error::InvalidFormat`] if `patch` does not start with a valid header.
+    pub fn read(patch: &[u8]) -> Result<Self> {
+        if patch.len() < HEADER_SIZE || patch[..4] != MAGIC[..4] {
+            return Err(Error::InvalidFormat("not a patch"));
+        } else if patch[4] != MAGIC[4] {
+            return Err(Error::InvalidFormat("unsupported patch version"));
+        }
+
+        let dict_size_log2 = patch[5] as u32;
+        if !(MIN_DICT_SIZE_LOG2..=MAX_DICT_SIZE_LOG2).contains(&dict_size_log2) {
+            return Err(Error::InvalidFormat("invalid dictionary size"));
+        }
+
+        Ok(Self {
+            dict_size_log2,
+            base_size: u64::from_be_bytes(patch[8..16].try_into().unwrap()),
+            base_crc32: u32::from_be_bytes(patch[16..20].try_into().unwrap()),
+            target_size: u64::from_be_bytes(patch[20..28].try_into().unwrap()),
+            target_crc32: u32::from_be_bytes(patch[28..32].try_into().unwrap()),
+        })
+    }
+
+    fn to_bytes(self) -> [u8; HEADER_SIZE] {
+        let mut header = [0; HEADER_SIZE];
+        header[..5].copy_from_slice(&MAGIC);
+        header[5] = self.dict_size_log2 as u8;
+        header[8..16].copy_from_slice(&self.base_size.to_be_bytes());
+        header[16..20].copy_from_slice(&self.base_crc32.to_be_bytes());
+        header[20..28].copy_from_slice(&self.target_size.to_be_bytes());
+        header[28..32].copy_from_slice(&self.target_crc32.to_be_bytes());
+
+        header
+    }
+}
+
+/// Creates a patch turning `old` into `new`, with default [`options`].
+///
+/// [`options`]: CompressionOptions
+pub fn diff(old: &[u8], new: &[u8]) -> Result<Vec<u8>> {
+    diff_with_options(old, new, &CompressionOptions::default())
+}
+
+/// Creates a patch turning `old` into `new`, with provided [`options`].
+///
+/// The dictionary size and the seed bytes of `options` are chosen from the sizes of `old`
+/// and `new`, and are ignored. The other options must be the same as the ones used by
+/// [`patch_with_options`].
+///
+/// [`options`]: CompressionOptions
+pub fn diff_with_options(old: &[u8], new: &[u8], options: &CompressionOptions) -> Result<Vec<u8>> {
+    let dict_size_log2 = dict_size_log2(old.len().saturating_add(new.len()));
+    let header = PatchHeader {
+        dict_size_log2,
+        base_size: old.len() as u64,
+        base_crc32: crc32(0, old),
+        target_size: new.len() as u64,
+        target_crc32: crc32(0, new),
+    };
+
+    let mut compressor = Compressor::from_options(CompressionOptions {
+        dict_size_log2,
+        num_seed_bytes: None,
+        p_seed_bytes: seed(old, dict_size_log2),
+        ..options.clone()
+    });
+
+    let mut output = Vec::with_capacity(HEADER_SIZE + new.len() / 4 + 64);
+    output.extend_from_slice(&header.to_bytes());
+    compressor.compress_to_vec_with_report(new, &mut output)?;
+
+    Ok(output)
+}
+
+/// Rebuilds the new data from `old` and a patch created by [`diff`].
+///
+/// It fails with [`Error::InvalidFormat`] if `delta` is not a patch, and with
+/// [`Error::BaseMismatch`] if it was not created from `old`. The rebuilt data is checked
+/// against the size and the checksum in the header.
+pub fn patch(old: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    patch_with_options(old, delta, &DecompressionOptions::default())
+}
+
+/// Rebuilds the new data from `old` and a patch created by [`diff_with_options`], with
+/// provided [`options`].
+///
+/// The dictionary size and the seed bytes of `options` are taken from the patch and `old`,
+/// and are ignored. See [`patch`] for details.
+///
+/// [`options`]: DecompressionOptions
+pub fn patch_with_options(
+    old: &[u8],
+    delta: &[u8],
+    options: &DecompressionOptions,
+) -> Result<Vec<u8>> {
+    let header = PatchHeader::read(delta)?;
+
+    let actual = crc32(0, old);
+    if header.base_size != old.len() as u64 || header.base_crc32 != actual {
+        return Err(Error::BaseMismatch {
+            expected: header.base_crc32,
+            actual,
+        });
+    }
+
+    let target_size = usize::try_from(header.target_size)
+        .map_err(|_| Error::InvalidFormat("invalid target size"))?;
+
+    let mut decompressor = Decompressor::from_options(DecompressionOptions {
+        dict_size_log2: header.dict_size_log2,
+        decompression_flags: Some(DecompressionFlag::ComputeAdler32),
+        num_seed_bytes: None,
+        p_seed_bytes: seed(old, header.dict_size_log2),
+        ..options.clone()
+    });
+
+    // The target size is not trusted for the allocation, as the patch may be damaged.
+    let stream = &delta[HEADER_SIZE..];
+    let mut output = Vec::with_capacity(target_size.min(stream.len().saturating_mul(4)));
+    decompressor.decompress_to_vec_with_report(stream, &mut output, target_size)?;
+
+    if output.len() != target_size {
+        return Err(Error::SizeMismatch {
+            expected: target_size,
+            actual: output.len(),
+        });
+    } else if crc32(0, &output) != header.target_crc32 {
+        return Err(Error::InvalidFormat("the patched data is damaged"));
+    }
+
+    Ok(output)
+}
+
+/// Returns the base 2 log of the smallest dictionary holding `len` bytes, within the limits
+/// of the native codec.
+fn dict_size_log2(len: usize) -> u32 {
+    let log2 = len
+        .checked_next_power_of_two()
+        .map_or(usize::BITS, usize::trailing_zeros);
+
+    log2.clamp(MIN_DICT_SIZE_LOG2, MAX_DICT_SIZE_LOG2)
+}
+
+/// Returns the seed bytes for `old`, which must fit in the dictionary.
+fn seed(old: &[u8], dict_size_log2: u32) -> Option<Vec<u8>> {
+    if old.is_empty() {
+        None
+    } else {
+        Some(seed_tail(old, dict_size_log2).to_vec())
+    }
+}
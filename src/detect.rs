@@ -0,0 +1,318 @@
+//! Detection of the format of compressed data from its leading bytes.
+//!
+//! [`detect`] tells the formats of this crate apart: raw LZHAM streams, zlib-wrapped streams,
+//! [framed streams], [dictionary-compressed data], [patches] and [archives].
+//! [`auto_decompress`] detects the format of a reader and decompresses it accordingly.
+//!
+//! A raw stream has no header, so it is only recognized by the header of its first block. Any
+//! data which is not in one of the other formats and starts with a valid block header is
+//! detected as raw, and may still fail to decompress.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::detect::{auto_decompress, detect, Format};
+//! # use std::io::Read;
+//! let comp = lzham::compress_to_vec(b"This is a test.", &Default::default()).unwrap();
+//!
+//! assert_eq!(detect(&comp), Format::Raw);
+//!
+//! let mut decomp = Vec::new();
+//! auto_decompress(comp.as_slice(), &Default::default())
+//!     .unwrap()
+//!     .read_to_end(&mut decomp)
+//!     .unwrap();
+//!
+//! assert_eq!(decomp, b"This is a test.");
+//! ```
+//!
+//! [framed streams]: crate::frame
+//! [dictionary-compressed data]: crate::dictionary
+//! [patches]: crate::delta
+//! [archives]: crate::archive
+
+use crate::{
+    archive, compress,
+    decompress::{Decoder, DecompressionFlag},
+    delta::{self, PatchHeader},
+    dictionary::{self, DictionaryId},
+    frame::{self, FrameReader},
+    low::{MAX_DICT_SIZE_LOG2, MIN_DICT_SIZE_LOG2},
+    DecompressionOptions, Error,
+};
+use std::{
+    convert::TryInto,
+    io::{self, BufRead, Read},
+};
+
+/// The number of leading bytes needed to detect every format.
+pub const PEEK_SIZE: usize = 32;
+
+/// The compression method of LZHAM in a zlib header.
+const ZLIB_METHOD: u8 = 14;
+
+/// The format of compressed data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A raw LZHAM stream, as produced by [`compress`](crate::compress()).
+    ///
+    /// The options it was compressed with are unknown.
+    Raw,
+    /// A zlib-wrapped LZHAM stream, as produced with
+    /// [`CompressionFlag::WriteZlibStream`](crate::compress::CompressionFlag::WriteZlibStream).
+    Zlib {
+        /// The base 2 log of the dictionary size.
+        dict_size_log2: u32,
+        /// The Adler-32 of the seed dictionary, if the stream was compressed with one.
+        seed_adler32: Option<u32>,
+    },
+    /// A framed stream, written by [`FrameWriter`](crate::frame::FrameWriter).
+    Framed,
+    /// Data compressed with the given seed dictionary by a
+    /// [`DictionaryRegistry`](crate::dictionary::DictionaryRegistry).
+    Dictionary(DictionaryId),
+    /// A patch created by [`diff`](crate::delta::diff), with its header.
+    Patch(PatchHeader),
+    /// An archive written by [`ArchiveWriter`](crate::archive::ArchiveWriter).
+    Archive,
+    /// None of the above.
+    Unknown,
+}
+
+/// Detects the format of the data starting with `input`.
+///
+/// `input` should hold the first [`PEEK_SIZE`] bytes of the data, or all of it if it is
+/// shorter. Empty data is [`Format::Unknown`].
+pub fn detect(input: &[u8]) -> Format {
+    // The formats of this crate all start with `LZH`, and the version must be supported.
+    if input.starts_with(&frame::MAGIC) {
+        return Format::Framed;
+    } else if input.starts_with(&archive::MAGIC) {
+        return Format::Archive;
+    } else if input.starts_with(&dictionary::MAGIC[..4]) {
+        return DictionaryId::read(input).map_or(Format::Unknown, Format::Dictionary);
+    } else if input.starts_with(&delta::MAGIC[..4]) {
+        return PatchHeader::read(input).map_or(Format::Unknown, Format::Patch);
+    } else if input.starts_with(b"LZH") {
+        return Format::Unknown;
+    } else if let Some(format) = detect_zlib(input) {
+        return format;
+    }
+
+    if detect_raw(input) {
+        Format::Raw
+    } else {
+        Format::Unknown
+    }
+}
+
+/// Checks the header of the first block of a raw stream.
+fn detect_raw(input: &[u8]) -> bool {
+    // The first 2 bits are the type of the block. A stream never starts with a sync block.
+    match input {
+        // A compressed block, followed by its flush type, which is never 3.
+        [byte, ..] if byte >> 6 == 1 => (byte >> 4) & 3 != 3,
+        // A raw block, followed by its length in 24 bits, and their xor in 8 bits.
+        [b0, b1, b2, b3, b4, ..] if b0 >> 6 == 2 => {
+            let header = u64::from_be_bytes([0, 0, 0, *b0, *b1, *b2, *b3, *b4]);
+            let [_, len0, len1, len2] = ((header >> 14) as u32 & 0xff_ffff).to_be_bytes();
+            (header >> 6) & 0xff == (len0 ^ len1 ^ len2) as u64
+        }
+        // The end of an empty stream, padded to a byte.
+        [byte, ..] => *byte == 0xc0,
+        [] => false,
+    }
+}
+
+/// Parses the zlib header written by the native codec.
+fn detect_zlib(input: &[u8]) -> Option<Format> {
+    let (cmf, flg) = match input {
+        [cmf, flg, ..] => (*cmf, *flg),
+        _ => return None,
+    };
+
+    let dict_size_log2 = (cmf >> 4) as u32 + MIN_DICT_SIZE_LOG2;
+    if cmf & 0x0f != ZLIB_METHOD
+        || dict_size_log2 > MAX_DICT_SIZE_LOG2
+        || u16::from_be_bytes([cmf, flg]) % 31 != 0
+    {
+        return None;
+    }
+
+    let seed_adler32 = if flg & 0x20 != 0 {
+        Some(u32::from_be_bytes(input.get(2..6)?.try_into().unwrap()))
+    } else {
+        None
+    };
+
+    Some(Format::Zlib {
+        dict_size_log2,
+        seed_adler32,
+    })
+}
+
+/// Detects the format of the data read from `reader`, and returns a reader decompressing
+/// it with provided [`options`].
+///
+/// Raw and framed streams are decompressed with `options` as they are. For zlib-wrapped
+/// streams, the dictionary size is taken from the header, and the decompression flags are
+/// replaced to read the header. A stream compressed with a seed dictionary needs the same
+/// seed bytes in `options`.
+///
+/// A raw stream has no header, and is only recognized by its first block. If it fails to
+/// decompress within its first block, reading fails with [`Error::UnknownFormat`] rather
+/// than a decompression error.
+///
+/// It fails with [`Error::UnknownFormat`] if the format is unknown, and with
+/// [`Error::InvalidFormat`] for dictionary-compressed data, patches and archives, which
+/// need more than a reader to be decompressed. Both are reported as
+/// [`io::ErrorKind::InvalidData`].
+///
+/// [`options`]: DecompressionOptions
+pub fn auto_decompress<R: BufRead>(
+    mut reader: R,
+    options: &DecompressionOptions,
+) -> io::Result<AutoDecoder<R>> {
+    let mut head = [0; PEEK_SIZE];
+    let mut len = 0;
+    while len < PEEK_SIZE {
+        match reader.read(&mut head[len..]) {
+            Ok(0) => break,
+            Ok(read) => len += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let format = detect(&head[..len]);
+    let reader = Peeked {
+        head,
+        pos: 0,
+        len,
+        inner: reader,
+    };
+
+    let inner = match format {
+        Format::Raw => Inner::Stream(Decoder::new(reader, options.clone())),
+        Format::Zlib { dict_size_log2, .. } => Inner::Stream(Decoder::new(
+            reader,
+            DecompressionOptions {
+                dict_size_log2,
+                decompression_flags: Some(DecompressionFlag::ReadZlibStream),
+                ..options.clone()
+            },
+        )),
+        Format::Framed => Inner::Framed(FrameReader::with_options(reader, options.clone())),
+        Format::Dictionary(_) => {
+            return Err(Error::InvalidFormat(
+                "dictionary-compressed data needs a dictionary registry",
+            )
+            .into())
+        }
+        Format::Patch(_) => return Err(Error::InvalidFormat("a patch needs the old data").into()),
+        Format::Archive => {
+            return Err(Error::InvalidFormat("an archive needs an archive reader").into())
+        }
+        Format::Unknown => return Err(Error::UnknownFormat.into()),
+    };
+
+    Ok(AutoDecoder {
+        format,
+        inner,
+        first_block: if format == Format::Raw {
+            compress::block_size(options.dict_size_log2)
+        } else {
+            0
+        },
+    })
+}
+
+/// Decompresses data in a detected format, created by [`auto_decompress`].
+#[derive(Debug)]
+pub struct AutoDecoder<R: BufRead> {
+    format: Format,
+    inner: Inner<R>,
+    // The size left in the first block of a raw stream, whose format is only a guess until
+    // then, or 0.
+    first_block: usize,
+}
+
+#[derive(Debug)]
+enum Inner<R: BufRead> {
+    Stream(Decoder<Peeked<R>>),
+    Framed(FrameReader<Peeked<R>>),
+}
+
+impl<R: BufRead> AutoDecoder<R> {
+    /// Returns the detected format.
+    pub fn format(&self) -> Format {
+        self.format
+    }
+}
+
+impl<R: BufRead> Read for AutoDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let result = match &mut self.inner {
+            Inner::Stream(decoder) => decoder.read(buf),
+            Inner::Framed(reader) => reader.read(buf),
+        };
+
+        match result {
+            Ok(read) => {
+                self.first_block = self.first_block.saturating_sub(read);
+                Ok(read)
+            }
+            Err(e)
+                if self.first_block > 0
+                    && matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) =>
+            {
+                Err(Error::UnknownFormat.into())
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Returns the bytes read for the detection before the rest of the inner reader.
+#[derive(Debug)]
+struct Peeked<R> {
+    head: [u8; PEEK_SIZE],
+    pos: usize,
+    len: usize,
+    inner: R,
+}
+
+impl<R: BufRead> Read for Peeked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.len {
+            return self.inner.read(buf);
+        }
+
+        let read = (self.len - self.pos).min(buf.len());
+        buf[..read].copy_from_slice(&self.head[self.pos..self.pos + read]);
+        self.pos += read;
+
+        Ok(read)
+    }
+}
+
+impl<R: BufRead> BufRead for Peeked<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.len {
+            self.inner.fill_buf()
+        } else {
+            Ok(&self.head[self.pos..self.len])
+        }
+    }
+
+    fn consume(&mut self, amt: usize) {
+        if self.pos == self.len {
+            self.inner.consume(amt);
+        } else {
+            self.pos = (self.pos + amt).min(self.len);
+        }
+    }
+}
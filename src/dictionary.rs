@@ -0,0 +1,197 @@
+//! Seed dictionaries identified by their content.
+//!
+//! Data compressed with a seed dictionary can only be decompressed with the same
+//! dictionary. When several dictionaries are in use, [`DictionaryRegistry`] keeps track of
+//! them by a [`DictionaryId`] computed from their content, records the ID in front of the
+//! compressed data, and looks the dictionary up again when decompressing.
+//!
+//! ## Format
+//!
+//! The compressed data starts with the magic bytes `LZHS`, the format version, `1`, and the
+//! 8 bytes big-endian ID of the dictionary. The LZHAM stream follows.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::{dictionary::DictionaryRegistry, DecompressedSize};
+//! let mut registry = DictionaryRegistry::new();
+//! let id = registry.insert(b"This is a test.".to_vec());
+//!
+//! let comp = registry
+//!     .compress_to_vec(id, b"This is another test.", &Default::default())
+//!     .unwrap();
+//! let decomp = registry
+//!     .decompress_to_vec(&comp, &Default::default(), DecompressedSize::Limit(1024))
+//!     .unwrap();
+//!
+//! assert_eq!(decomp, b"This is another test.");
+//! ```
+
+use crate::{
+    compress_to_vec, decompress_to_vec, low::seed_tail, CompressionOptions, DecompressedSize,
+    DecompressionOptions, Error, Result,
+};
+use std::{collections::HashMap, convert::TryInto, fmt, sync::Arc};
+
+/// The bytes starting the compressed data.
+pub(crate) const MAGIC: [u8; 5] = [b'L', b'Z', b'H', b'S', 1];
+
+/// The size of the header in front of the LZHAM stream.
+const HEADER_SIZE: usize = 13;
+
+/// The ID of a seed dictionary.
+///
+/// It is the 64-bit FNV-1a hash of the content of the dictionary, so it is stable across
+/// processes, platforms and versions of this crate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DictionaryId(pub u64);
+
+impl DictionaryId {
+    /// Computes the ID of `dictionary`.
+    pub fn of(dictionary: &[u8]) -> Self {
+        const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        let hash = dictionary.iter().fold(OFFSET_BASIS, |hash, &byte| {
+            (hash ^ byte as u64).wrapping_mul(PRIME)
+        });
+
+        Self(hash)
+    }
+
+    /// Reads the ID recorded in front of data compressed by a [`DictionaryRegistry`].
+    ///
+    /// It fails with [`Error::InvalidFormat`] if the data does not start with a header.
+    pub fn read(input: &[u8]) -> Result<Self> {
+        if input.len() < HEADER_SIZE || input[..4] != MAGIC[..4] {
+            return Err(Error::InvalidFormat("no dictionary header"));
+        } else if input[4] != MAGIC[4] {
+            return Err(Error::InvalidFormat(
+                "unsupported dictionary header version",
+            ));
+        }
+
+        Ok(Self(u64::from_be_bytes(
+            input[5..HEADER_SIZE].try_into().unwrap(),
+        )))
+    }
+}
+
+impl fmt::Display for DictionaryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// A set of seed dictionaries, indexed by their [`DictionaryId`].
+///
+/// The dictionaries are shared by the clones of a registry.
+#[derive(Clone, Debug, Default)]
+pub struct DictionaryRegistry {
+    dictionaries: HashMap<DictionaryId, Arc<[u8]>>,
+}
+
+impl DictionaryRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a dictionary to the registry and returns its ID.
+    ///
+    /// Adding the same dictionary twice has no effect.
+    pub fn insert(&mut self, dictionary: Vec<u8>) -> DictionaryId {
+        let id = DictionaryId::of(&dictionary);
+        self.dictionaries
+            .entry(id)
+            .or_insert_with(|| dictionary.into());
+
+        id
+    }
+
+    /// Removes a dictionary from the registry, and returns it if it was present.
+    pub fn remove(&mut self, id: DictionaryId) -> Option<Arc<[u8]>> {
+        self.dictionaries.remove(&id)
+    }
+
+    /// Returns the dictionary with the given ID.
+    pub fn get(&self, id: DictionaryId) -> Option<&[u8]> {
+        self.dictionaries.get(&id).map(|dictionary| &dictionary[..])
+    }
+
+    /// Returns `true` if the registry holds a dictionary with the given ID.
+    pub fn contains(&self, id: DictionaryId) -> bool {
+        self.dictionaries.contains_key(&id)
+    }
+
+    /// Returns the IDs of the dictionaries in the registry, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = DictionaryId> + '_ {
+        self.dictionaries.keys().copied()
+    }
+
+    /// Returns the number of dictionaries in the registry.
+    pub fn len(&self) -> usize {
+        self.dictionaries.len()
+    }
+
+    /// Returns `true` if the registry holds no dictionary.
+    pub fn is_empty(&self) -> bool {
+        self.dictionaries.is_empty()
+    }
+
+    /// Compresses `input` with the dictionary `id` and provided [`options`], into a new
+    /// vector starting with the ID.
+    ///
+    /// The seed bytes of `options` are replaced by the dictionary. If the dictionary is
+    /// larger than the dictionary size, only its end is used. It fails with
+    /// [`Error::DictionaryNotFound`] if the registry does not hold the dictionary.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn compress_to_vec(
+        &self,
+        id: DictionaryId,
+        input: &[u8],
+        options: &CompressionOptions,
+    ) -> Result<Vec<u8>> {
+        let dictionary = self.get(id).ok_or(Error::DictionaryNotFound(id))?;
+        let options = CompressionOptions {
+            num_seed_bytes: None,
+            p_seed_bytes: Some(seed_tail(dictionary, options.dict_size_log2).to_vec()),
+            ..options.clone()
+        };
+
+        let mut output = Vec::with_capacity(HEADER_SIZE + input.len() / 2 + 64);
+        output.extend_from_slice(&MAGIC);
+        output.extend_from_slice(&id.0.to_be_bytes());
+        output.extend(compress_to_vec(input, &options)?);
+
+        Ok(output)
+    }
+
+    /// Decompresses data produced by [`compress_to_vec`] with provided [`options`], looking
+    /// up the dictionary from the ID it starts with.
+    ///
+    /// The seed bytes of `options` are replaced by the dictionary. It fails with
+    /// [`Error::InvalidFormat`] if `input` does not start with an ID, and with
+    /// [`Error::DictionaryNotFound`] if the registry does not hold the dictionary. See
+    /// [`crate::decompress_to_vec`] for the meaning of `size`.
+    ///
+    /// [`compress_to_vec`]: DictionaryRegistry::compress_to_vec
+    /// [`options`]: DecompressionOptions
+    pub fn decompress_to_vec(
+        &self,
+        input: &[u8],
+        options: &DecompressionOptions,
+        size: DecompressedSize,
+    ) -> Result<Vec<u8>> {
+        let id = DictionaryId::read(input)?;
+        let dictionary = self.get(id).ok_or(Error::DictionaryNotFound(id))?;
+        let options = DecompressionOptions {
+            num_seed_bytes: None,
+            p_seed_bytes: Some(seed_tail(dictionary, options.dict_size_log2).to_vec()),
+            ..options.clone()
+        };
+
+        decompress_to_vec(&input[HEADER_SIZE..], &options, size)
+    }
+}
@@ -0,0 +1,241 @@
+//! Error type of the crate.
+
+use crate::{
+    compress::CompressionStatus,
+    decompress::{DecompressionOptions, DecompressionStatus},
+    dictionary::DictionaryId,
+};
+use std::{fmt, io};
+
+/// A specialized [`Result`](std::result::Result) type for compression and decompression.
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The details of a failed decompression.
+#[derive(Clone, Debug)]
+pub struct DecompressionFailure {
+    /// The status the codec failed with.
+    pub status: DecompressionStatus,
+    /// The number of compressed bytes consumed when the codec failed.
+    pub compressed_offset: u64,
+    /// The number of decompressed bytes produced when the codec failed.
+    pub uncompressed_offset: u64,
+    /// The status of the last successful call to the codec, if any.
+    pub last_status: Option<DecompressionStatus>,
+    /// Whether the codec had consumed all of the input when it failed.
+    pub input_exhausted: bool,
+    /// The index of the failing stream, when decoding [concatenated streams].
+    ///
+    /// The offsets are then relative to the start of the whole input and output.
+    ///
+    /// [concatenated streams]: crate::multi
+    pub member: Option<usize>,
+    /// The options the decompressor was using.
+    pub options: DecompressionOptions,
+}
+
+/// The likely cause of a failed decompression, as guessed by [`DecompressionFailure::cause`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureCause {
+    /// The input ended before the end of the stream.
+    ///
+    /// The native codec only detects it in blocks stored uncompressed. Elsewhere, it decodes
+    /// past the end of the input as if it was padded with zeros, so a truncated stream
+    /// usually produces garbage until the output limit is reached.
+    Truncated,
+    /// The options do not match the ones the stream was compressed with.
+    ///
+    /// A mismatch in the dictionary size or the table update settings is usually reported
+    /// as [`FailureCause::Corrupted`], as the codec cannot tell it apart from damaged data.
+    ParameterMismatch,
+    /// The compressed data is damaged.
+    Corrupted,
+}
+
+impl DecompressionFailure {
+    /// Guesses the cause of the failure from the status and whether the input was exhausted.
+    pub fn cause(&self) -> FailureCause {
+        match self.status {
+            DecompressionStatus::NeedSeedBytes
+            | DecompressionStatus::BadSeedBytes
+            | DecompressionStatus::InvalidParameter
+            | DecompressionStatus::FailedInitialization
+            | DecompressionStatus::BadZlibHeader => FailureCause::ParameterMismatch,
+            _ if self.input_exhausted => FailureCause::Truncated,
+            _ => FailureCause::Corrupted,
+        }
+    }
+}
+
+impl fmt::Display for DecompressionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "decompression failed: {:?}", self.status)?;
+
+        if let Some(member) = self.member {
+            write!(f, " in member {}", member)?;
+        }
+        write!(
+            f,
+            " at compressed offset {}, uncompressed offset {}",
+            self.compressed_offset, self.uncompressed_offset
+        )?;
+
+        if let Some(status) = self.last_status {
+            write!(f, ", after {:?}", status)?;
+        }
+        if self.input_exhausted {
+            write!(f, ", at the end of the input")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The error type for compression and decompression.
+#[derive(Debug)]
+pub enum Error {
+    /// The compression failed with the given status.
+    Compression(CompressionStatus),
+    /// The decompression failed with the given status.
+    Decompression(DecompressionStatus),
+    /// The decompression failed, with details about where and how.
+    ///
+    /// It is returned instead of [`Error::Decompression`] when the position of the failure
+    /// is known.
+    DecompressionFailure(Box<DecompressionFailure>),
+    /// The output buffer is too small to hold the result.
+    ///
+    /// It contains the required size of the output buffer, if it is known.
+    OutputBufferTooSmall(Option<usize>),
+    /// The decompressed data is smaller than the expected size.
+    SizeMismatch {
+        /// The expected size of the decompressed data.
+        expected: usize,
+        /// The actual size of the decompressed data.
+        actual: usize,
+    },
+    /// The data is not in the expected format, or is damaged.
+    InvalidFormat(&'static str),
+    /// A patch was not created from the given base data.
+    BaseMismatch {
+        /// The CRC-32 of the base data the patch was created from.
+        expected: u32,
+        /// The CRC-32 of the given base data.
+        actual: u32,
+    },
+    /// The seed dictionary with the given ID is not in the [`DictionaryRegistry`].
+    ///
+    /// [`DictionaryRegistry`]: crate::dictionary::DictionaryRegistry
+    DictionaryNotFound(DictionaryId),
+    /// The archive has no entry with the given name.
+    EntryNotFound(String),
+    /// The data is not in any of the formats recognized by [`detect`].
+    ///
+    /// [`detect`]: crate::detect::detect
+    UnknownFormat,
+    /// The operation was cancelled with a [`CancellationToken`].
+    ///
+    /// [`CancellationToken`]: crate::progress::CancellationToken
+    Cancelled,
+    /// An I/O error occurred while reading the input or writing the output.
+    Io(io::Error),
+    /// An error occurred in a stream of [concatenated streams].
+    ///
+    /// It wraps every error but [`Error::DecompressionFailure`], which holds the index of the
+    /// stream itself.
+    ///
+    /// [concatenated streams]: crate::multi
+    Member {
+        /// The index of the failing stream.
+        member: usize,
+        /// The error of the stream.
+        error: Box<Error>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Compression(status) => write!(f, "compression failed: {:?}", status),
+            Self::Decompression(status) => write!(f, "decompression failed: {:?}", status),
+            Self::DecompressionFailure(failure) => failure.fmt(f),
+            Self::OutputBufferTooSmall(Some(size)) => {
+                write!(f, "output buffer too small, {} bytes required", size)
+            }
+            Self::OutputBufferTooSmall(None) => write!(f, "output buffer too small"),
+            Self::SizeMismatch { expected, actual } => write!(
+                f,
+                "decompressed {} bytes, expected {} bytes",
+                actual, expected
+            ),
+            Self::InvalidFormat(reason) => write!(f, "invalid format: {}", reason),
+            Self::BaseMismatch { expected, actual } => write!(
+                f,
+                "base data mismatch, expected CRC-32 {:08x}, got {:08x}",
+                expected, actual
+            ),
+            Self::DictionaryNotFound(id) => write!(f, "dictionary {} not found", id),
+            Self::EntryNotFound(name) => write!(f, "entry {} not found", name),
+            Self::UnknownFormat => write!(f, "unknown format"),
+            Self::Cancelled => write!(f, "operation cancelled"),
+            Self::Io(e) => write!(f, "I/O error: {}", e),
+            Self::Member { member, error } => write!(f, "{} in member {}", error, member),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Member { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl Error {
+    /// Returns the status of a failed decompression.
+    ///
+    /// It is set for both [`Error::Decompression`] and [`Error::DecompressionFailure`], also
+    /// within an [`Error::Member`].
+    pub fn decompression_status(&self) -> Option<DecompressionStatus> {
+        match self {
+            Self::Decompression(status) => Some(*status),
+            Self::DecompressionFailure(failure) => Some(failure.status),
+            Self::Member { error, .. } => error.decompression_status(),
+            _ => None,
+        }
+    }
+
+    /// Returns the kind of the I/O error the error converts to.
+    fn io_kind(&self) -> io::ErrorKind {
+        match self {
+            Self::Io(e) => e.kind(),
+            Self::Member { error, .. } => error.io_kind(),
+            Self::Decompression(_)
+            | Self::DecompressionFailure(_)
+            | Self::SizeMismatch { .. }
+            | Self::InvalidFormat(_)
+            | Self::UnknownFormat => io::ErrorKind::InvalidData,
+            Self::BaseMismatch { .. } | Self::DictionaryNotFound(_) => io::ErrorKind::InvalidInput,
+            Self::EntryNotFound(_) => io::ErrorKind::NotFound,
+            // Not `Interrupted`, which the std helpers retry.
+            _ => io::ErrorKind::Other,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            e => io::Error::new(e.io_kind(), e),
+        }
+    }
+}
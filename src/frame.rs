@@ -0,0 +1,432 @@
+//! A chunked framing format for streams of unknown size.
+//!
+//! The data is split into chunks which are compressed independently, like in the Snappy
+//! framing format. The total size does not need to be known up front, damaged chunks and
+//! truncated streams are detected, and streams can be appended to each other.
+//!
+//! ## Format
+//!
+//! A stream starts with the magic bytes `LZHF`, followed by the format version, `1`. Then
+//! come the chunks, each with a 13 bytes header:
+//!
+//! | Offset | Size | Field                                   |
+//! |--------|------|-----------------------------------------|
+//! | 0      | 1    | The type of the chunk                   |
+//! | 1      | 4    | The length of the payload               |
+//! | 5      | 4    | The length of the uncompressed data     |
+//! | 9      | 4    | The CRC-32 of the uncompressed data     |
+//!
+//! Integers are big-endian. The types of chunk are:
+//!
+//! - `0x00`: the payload is a complete LZHAM stream, smaller than the uncompressed data.
+//! - `0x01`: the payload is the uncompressed data, stored as is.
+//! - `0xFF`: the end of the stream. Both lengths are zero, and the checksum is the CRC-32 of
+//!   all the uncompressed data of the stream.
+//!
+//! The uncompressed data of a chunk is at most 4 MiB. A stream without its end chunk is
+//! truncated. Another stream may follow the end chunk.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::frame::{FrameReader, FrameWriter};
+//! # use std::io::{Read, Write};
+//! let mut writer = FrameWriter::new(Vec::new());
+//! writer.write_all(b"This is a test.").unwrap();
+//! let comp = writer.finish().unwrap();
+//!
+//! let mut decomp = Vec::new();
+//! FrameReader::new(comp.as_slice()).read_to_end(&mut decomp).unwrap();
+//!
+//! assert_eq!(decomp, b"This is a test.");
+//! ```
+
+use crate::{
+    compress::{CompressionFlush, Compressor},
+    compress_bound,
+    decompress::Decompressor,
+    low::crc32,
+    CompressionOptions, DecompressionOptions, Error,
+};
+use std::{
+    convert::TryInto,
+    io::{self, BufRead, Read, Write},
+};
+
+/// The bytes starting every stream.
+pub(crate) const MAGIC: [u8; 5] = [b'L', b'Z', b'H', b'F', 1];
+
+/// The size of a chunk header.
+const HEADER_SIZE: usize = 13;
+
+/// The maximum size of the uncompressed data of a chunk.
+pub const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+/// The default size of the uncompressed data of a chunk.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+const CHUNK_COMPRESSED: u8 = 0x00;
+const CHUNK_STORED: u8 = 0x01;
+const CHUNK_END: u8 = 0xFF;
+
+/// Compresses the data written to it into a framed stream.
+///
+/// The data is buffered until a whole chunk is available, or until it is flushed.
+/// [`finish`] must be called to write the end of the stream, otherwise the stream is read
+/// as truncated.
+///
+/// [`finish`]: FrameWriter::finish
+#[derive(Debug)]
+pub struct FrameWriter<W: Write> {
+    inner: W,
+    compressor: Compressor,
+    buf: Vec<u8>,
+    out: Vec<u8>,
+    chunk_size: usize,
+    crc: u32,
+    started: bool,
+}
+
+/// Decompresses a framed stream read from a [`BufRead`].
+///
+/// It reads streams until the end of the input, so appended streams are decompressed as
+/// one. It fails with [`io::ErrorKind::InvalidData`] if a chunk is damaged, and with
+/// [`io::ErrorKind::UnexpectedEof`] if a stream is truncated. An empty input holds no
+/// stream and is not an error.
+#[derive(Debug)]
+pub struct FrameReader<R: BufRead> {
+    inner: R,
+    decompressor: Decompressor,
+    buf: Vec<u8>,
+    pos: usize,
+    comp: Vec<u8>,
+    crc: u32,
+    in_stream: bool,
+}
+
+impl<W: Write> FrameWriter<W> {
+    /// Creates a new writer with default [`options`].
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, CompressionOptions::default())
+    }
+
+    /// Creates a new writer with provided [`options`].
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn with_options(inner: W, options: CompressionOptions) -> Self {
+        Self {
+            inner,
+            compressor: Compressor::from_options(options),
+            buf: Vec::new(),
+            out: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            crc: 0,
+            started: false,
+        }
+    }
+
+    /// Returns the size of the uncompressed data of a chunk.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// Sets the size of the uncompressed data of a chunk.
+    ///
+    /// Larger chunks compress better, but are buffered whole on both sides. It defaults to
+    /// 64 KiB, and is clamped between one byte and [`MAX_CHUNK_SIZE`].
+    pub fn set_chunk_size(&mut self, chunk_size: usize) {
+        self.chunk_size = chunk_size.clamp(1, MAX_CHUNK_SIZE);
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer.
+    ///
+    /// Writing to it directly corrupts the stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Writes the buffered data and the end of the stream, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_chunk()?;
+        self.write_magic()?;
+
+        let mut header = [0; HEADER_SIZE];
+        header[0] = CHUNK_END;
+        header[9..].copy_from_slice(&self.crc.to_be_bytes());
+        self.inner.write_all(&header)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+
+    fn write_magic(&mut self) -> io::Result<()> {
+        if !self.started {
+            self.inner.write_all(&MAGIC)?;
+            self.started = true;
+        }
+
+        Ok(())
+    }
+
+    /// Compresses and writes the buffered data as a chunk.
+    fn write_chunk(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+
+        self.write_magic()?;
+        self.compress()?;
+
+        let (kind, payload) = if self.out.len() < self.buf.len() {
+            (CHUNK_COMPRESSED, &self.out)
+        } else {
+            (CHUNK_STORED, &self.buf)
+        };
+
+        let mut header = [0; HEADER_SIZE];
+        header[0] = kind;
+        header[1..5].copy_from_slice(&(payload.len() as u32).to_be_bytes());
+        header[5..9].copy_from_slice(&(self.buf.len() as u32).to_be_bytes());
+        header[9..].copy_from_slice(&crc32(0, &self.buf).to_be_bytes());
+
+        self.inner.write_all(&header)?;
+        self.inner.write_all(payload)?;
+
+        self.crc = crc32(self.crc, &self.buf);
+        self.buf.clear();
+
+        Ok(())
+    }
+
+    /// Compresses the buffered data into a complete LZHAM stream.
+    fn compress(&mut self) -> io::Result<()> {
+        let bound = compress_bound(self.buf.len(), self.compressor.options());
+        self.out.resize(bound, 0);
+
+        let mut input = &self.buf[..];
+        let mut len = 0;
+        let result = loop {
+            // The bound is derived from the format, not guaranteed by the native codec, so
+            // the buffer grows instead of leaving the compressor without space.
+            if len == self.out.len() {
+                self.out.resize(len + bound, 0);
+            }
+
+            let (status, read, written) =
+                self.compressor
+                    .compress_buf(input, &mut self.out[len..], CompressionFlush::Finish);
+
+            input = &input[read..];
+            len += written;
+
+            if status.is_success() {
+                break Ok(());
+            } else if status.is_success_or_first_failure() {
+                break Err(Error::Compression(status));
+            }
+        };
+
+        self.out.truncate(len);
+        // The compressor must be ready for the next chunk, even if this one failed.
+        if !self.compressor.reinit() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to reinitialize the compressor",
+            ));
+        }
+
+        result.map_err(Into::into)
+    }
+}
+
+impl<W: Write> Write for FrameWriter<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if self.buf.len() >= self.chunk_size {
+            self.write_chunk()?;
+        }
+
+        let len = data.len().min(self.chunk_size - self.buf.len());
+        self.buf.extend_from_slice(&data[..len]);
+
+        if self.buf.len() == self.chunk_size {
+            self.write_chunk()?;
+        }
+
+        Ok(len)
+    }
+
+    /// Writes the buffered data as a chunk, so it can be decompressed, and flushes the inner
+    /// writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_chunk()?;
+        self.inner.flush()
+    }
+}
+
+impl<R: BufRead> FrameReader<R> {
+    /// Creates a new reader with default [`options`].
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn new(inner: R) -> Self {
+        Self::with_options(inner, DecompressionOptions::default())
+    }
+
+    /// Creates a new reader with provided [`options`].
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn with_options(inner: R, options: DecompressionOptions) -> Self {
+        Self {
+            inner,
+            decompressor: Decompressor::from_options(options),
+            buf: Vec::new(),
+            pos: 0,
+            comp: Vec::new(),
+            crc: 0,
+            in_stream: false,
+        }
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    ///
+    /// Reading from it directly corrupts the stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Consumes the reader and returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads and decompresses the next chunk with data into the buffer.
+    ///
+    /// Returns false at the end of the input.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        self.buf.clear();
+        self.pos = 0;
+
+        let result = self.read_chunk();
+        // The data of a damaged chunk must not be returned.
+        if result.is_err() {
+            self.buf.clear();
+        }
+
+        result
+    }
+
+    fn read_chunk(&mut self) -> io::Result<bool> {
+        loop {
+            if !self.in_stream {
+                if self.inner.fill_buf()?.is_empty() {
+                    return Ok(false);
+                }
+
+                let mut magic = [0; MAGIC.len()];
+                self.inner.read_exact(&mut magic)?;
+                if magic != MAGIC {
+                    return Err(invalid_data("invalid stream header"));
+                }
+
+                self.in_stream = true;
+                self.crc = 0;
+            }
+
+            let mut header = [0; HEADER_SIZE];
+            self.inner.read_exact(&mut header)?;
+
+            let kind = header[0];
+            let comp_len = u32::from_be_bytes(header[1..5].try_into().unwrap()) as usize;
+            let len = u32::from_be_bytes(header[5..9].try_into().unwrap()) as usize;
+            let crc = u32::from_be_bytes(header[9..].try_into().unwrap());
+
+            match kind {
+                CHUNK_END if comp_len == 0 && len == 0 => {
+                    if crc != self.crc {
+                        return Err(invalid_data("stream checksum mismatch"));
+                    }
+
+                    self.in_stream = false;
+                    continue;
+                }
+                CHUNK_COMPRESSED if comp_len < len && len <= MAX_CHUNK_SIZE => {
+                    self.comp.resize(comp_len, 0);
+                    self.inner.read_exact(&mut self.comp)?;
+                    self.decompress(len)?;
+                }
+                CHUNK_STORED if comp_len == len && len <= MAX_CHUNK_SIZE => {
+                    self.buf.resize(len, 0);
+                    self.inner.read_exact(&mut self.buf)?;
+                }
+                _ => return Err(invalid_data("invalid chunk header")),
+            }
+
+            if crc32(0, &self.buf) != crc {
+                return Err(invalid_data("chunk checksum mismatch"));
+            }
+
+            self.crc = crc32(self.crc, &self.buf);
+
+            return Ok(true);
+        }
+    }
+
+    /// Decompresses the payload of a compressed chunk into the buffer.
+    fn decompress(&mut self, len: usize) -> io::Result<()> {
+        self.buf.reserve(len);
+
+        let status = self
+            .decompressor
+            .decompress_to_vec(&self.comp, &mut self.buf, len);
+
+        if !self.decompressor.reset() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to reinitialize the decompressor",
+            ));
+        }
+
+        if !status.is_success() {
+            Err(Error::Decompression(status).into())
+        } else if self.buf.len() != len {
+            Err(Error::SizeMismatch {
+                expected: len,
+                actual: self.buf.len(),
+            }
+            .into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl<R: BufRead> Read for FrameReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if out.is_empty() || !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
@@ -0,0 +1,40 @@
+//! HTTP middleware for the `lzham` content coding, as a [`tower`] layer.
+//!
+//! [`LzhamLayer`] wraps an HTTP service. Request bodies sent with `Content-Encoding: lzham`
+//! are decompressed before they reach the service, and response bodies are compressed when
+//! the client lists `lzham` in its `Accept-Encoding` header. The bodies are streamed through
+//! the native codec frame by frame with [`LzhamBody`], so they are never held in memory.
+//!
+//! Small responses and responses whose content type is not in the configured list are sent
+//! as they are.
+//!
+//! This module requires the `http` feature.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # fn run<S>(service: S) {
+//! use lzham::http::LzhamLayer;
+//! use tower_layer::Layer;
+//!
+//! let mut layer = LzhamLayer::new();
+//! layer.set_min_size(256);
+//! layer.set_max_request_size(Some(16 * 1024 * 1024));
+//!
+//! let service = layer.layer(service);
+//! # }
+//! ```
+//!
+//! [`tower`]: https://crates.io/crates/tower
+
+mod body;
+mod layer;
+
+pub use body::LzhamBody;
+pub use layer::{Lzham, LzhamLayer, ResponseFuture};
+
+/// The error type of [`LzhamBody`].
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The name of the content coding.
+const ENCODING: &str = "lzham";
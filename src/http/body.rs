@@ -0,0 +1,341 @@
+use super::BoxError;
+use crate::{
+    compress::{CompressionFlush, Encoder},
+    decompress::Decompressor,
+    low::BUF_SIZE,
+    CompressionOptions, DecompressionOptions, Error,
+};
+use bytes::{Buf, Bytes, BytesMut};
+use http::HeaderMap;
+use http_body::{Body, Frame, SizeHint};
+use pin_project_lite::pin_project;
+use std::{
+    fmt, io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// An HTTP body which compresses or decompresses the data of an inner body.
+    ///
+    /// The data frames of the inner body are passed to the codec as they arrive, and the
+    /// output is yielded as soon as the codec produces it. Trailers are passed through after
+    /// the data. An identity body yields the inner frames unchanged.
+    pub struct LzhamBody<B> {
+        #[pin]
+        inner: B,
+        state: State,
+    }
+}
+
+enum State {
+    Identity,
+    Compress(Box<Encode>),
+    Decompress(Box<Decode>),
+}
+
+impl<B: Body> LzhamBody<B> {
+    /// Creates a body which yields the data of `inner` unchanged.
+    pub fn identity(inner: B) -> Self {
+        Self {
+            inner,
+            state: State::Identity,
+        }
+    }
+
+    /// Creates a body which compresses the data of `inner` with provided [`options`].
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn compress(inner: B, options: CompressionOptions) -> Self {
+        Self {
+            inner,
+            state: State::Compress(Box::new(Encode {
+                encoder: Encoder::new(options),
+                trailers: None,
+                finishing: false,
+                done: false,
+            })),
+        }
+    }
+
+    /// Creates a body which decompresses the data of `inner` with provided [`options`].
+    ///
+    /// It fails if the decompressed data is larger than `limit` bytes, if the compressed
+    /// stream is damaged or incomplete, or if data follows the end of the stream.
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn decompress(inner: B, options: DecompressionOptions, limit: Option<u64>) -> Self {
+        Self {
+            inner,
+            state: State::Decompress(Box::new(Decode {
+                decompressor: Decompressor::from_options(options),
+                buf: vec![0; BUF_SIZE].into_boxed_slice(),
+                input: Bytes::new(),
+                pending: false,
+                eof: false,
+                finished: false,
+                total: 0,
+                limit,
+            })),
+        }
+    }
+
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Consumes the body and returns the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for LzhamBody<B>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    type Data = Bytes;
+    type Error = BoxError;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>> {
+        let this = self.project();
+
+        match this.state {
+            State::Identity => match ready!(this.inner.poll_frame(cx)) {
+                Some(Ok(frame)) => Poll::Ready(Some(Ok(
+                    frame.map_data(|mut data| data.copy_to_bytes(data.remaining()))
+                ))),
+                Some(Err(e)) => Poll::Ready(Some(Err(e.into()))),
+                None => Poll::Ready(None),
+            },
+            State::Compress(encode) => encode.poll_frame(this.inner, cx),
+            State::Decompress(decode) => decode.poll_frame(this.inner, cx),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match &self.state {
+            State::Identity => self.inner.is_end_stream(),
+            State::Compress(encode) => encode.done && encode.trailers.is_none(),
+            State::Decompress(_) => false,
+        }
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match &self.state {
+            State::Identity => self.inner.size_hint(),
+            _ => SizeHint::default(),
+        }
+    }
+}
+
+impl<B> fmt::Debug for LzhamBody<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.state {
+            State::Identity => "identity",
+            State::Compress(_) => "compress",
+            State::Decompress(_) => "decompress",
+        };
+
+        f.debug_struct("LzhamBody").field("mode", &mode).finish()
+    }
+}
+
+/// The state of a compressing body.
+struct Encode {
+    encoder: Encoder,
+    trailers: Option<HeaderMap>,
+    // Whether the inner body has ended.
+    finishing: bool,
+    // Whether the stream is finished.
+    done: bool,
+}
+
+impl Encode {
+    fn poll_frame<B>(
+        &mut self,
+        mut inner: Pin<&mut B>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>>
+    where
+        B: Body,
+        B::Error: Into<BoxError>,
+    {
+        loop {
+            if self.done {
+                return Poll::Ready(
+                    self.trailers
+                        .take()
+                        .map(|trailers| Ok(Frame::trailers(trailers))),
+                );
+            } else if self.finishing {
+                let output = self.finish()?;
+                self.done = true;
+
+                if !output.is_empty() {
+                    return Poll::Ready(Some(Ok(Frame::data(output))));
+                }
+                continue;
+            }
+
+            match ready!(inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(mut data) => {
+                        let output = self.compress(&data.copy_to_bytes(data.remaining()))?;
+
+                        if !output.is_empty() {
+                            return Poll::Ready(Some(Ok(Frame::data(output))));
+                        }
+                    }
+                    Err(frame) => {
+                        if let Ok(trailers) = frame.into_trailers() {
+                            self.trailers = Some(trailers);
+                        }
+                    }
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => self.finishing = true,
+            }
+        }
+    }
+
+    /// Passes all of `input` to the codec, and returns all the output it produced.
+    fn compress(&mut self, mut input: &[u8]) -> Result<Bytes, Error> {
+        let mut output = BytesMut::new();
+
+        while !input.is_empty() {
+            let read = self.encoder.compress(input)?;
+            output.extend_from_slice(self.encoder.output());
+            input = &input[read..];
+        }
+
+        Ok(output.freeze())
+    }
+
+    /// Finishes the stream, and returns all the output the codec produced.
+    fn finish(&mut self) -> Result<Bytes, Error> {
+        let mut output = BytesMut::new();
+
+        loop {
+            let complete = self.encoder.flush(CompressionFlush::Finish)?;
+            output.extend_from_slice(self.encoder.output());
+
+            if complete {
+                return Ok(output.freeze());
+            }
+        }
+    }
+}
+
+/// The state of a decompressing body.
+struct Decode {
+    decompressor: Decompressor,
+    buf: Box<[u8]>,
+    // The compressed data not passed to the codec yet.
+    input: Bytes,
+    // Whether the last call to the codec produced output, so it may have more.
+    pending: bool,
+    // Whether the inner body has ended.
+    eof: bool,
+    // Whether the stream is finished.
+    finished: bool,
+    total: u64,
+    limit: Option<u64>,
+}
+
+impl Decode {
+    fn poll_frame<B>(
+        &mut self,
+        mut inner: Pin<&mut B>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, BoxError>>>
+    where
+        B: Body,
+        B::Error: Into<BoxError>,
+    {
+        loop {
+            if !self.finished && (!self.input.is_empty() || self.pending) {
+                let (status, read, written) =
+                    self.decompressor
+                        .decompress_buf(&self.input, &mut self.buf, false);
+                self.input.advance(read);
+                self.pending = written > 0;
+                self.total += written as u64;
+
+                if status.is_success() {
+                    self.finished = true;
+                } else if status.is_success_or_first_failure() {
+                    return Poll::Ready(Some(Err(Error::Decompression(status).into())));
+                }
+
+                if let Some(limit) = self.limit {
+                    if self.total > limit {
+                        return Poll::Ready(Some(Err(too_large(limit))));
+                    }
+                }
+
+                if written > 0 {
+                    return Poll::Ready(Some(Ok(Frame::data(Bytes::copy_from_slice(
+                        &self.buf[..written],
+                    )))));
+                } else if read > 0 {
+                    continue;
+                }
+            }
+
+            if self.finished && !self.input.is_empty() {
+                return Poll::Ready(Some(Err(trailing_data())));
+            } else if self.eof {
+                return Poll::Ready(if self.finished {
+                    None
+                } else {
+                    Some(Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()))
+                });
+            }
+
+            match ready!(inner.as_mut().poll_frame(cx)) {
+                Some(Ok(frame)) => match frame.into_data() {
+                    Ok(mut data) if self.input.is_empty() => {
+                        self.input = data.copy_to_bytes(data.remaining());
+                    }
+                    Ok(mut data) => {
+                        let mut input = BytesMut::from(&self.input[..]);
+                        input.extend_from_slice(&data.copy_to_bytes(data.remaining()));
+                        self.input = input.freeze();
+                    }
+                    Err(frame) => match frame.into_trailers() {
+                        Ok(trailers) if self.finished => {
+                            return Poll::Ready(Some(Ok(Frame::trailers(trailers))));
+                        }
+                        // Trailers end the body, so the stream is incomplete.
+                        Ok(_) => self.eof = true,
+                        Err(_) => {}
+                    },
+                },
+                Some(Err(e)) => return Poll::Ready(Some(Err(e.into()))),
+                None => self.eof = true,
+            }
+        }
+    }
+}
+
+fn too_large(limit: u64) -> BoxError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("decompressed body exceeds the limit of {} bytes", limit),
+    )
+    .into()
+}
+
+fn trailing_data() -> BoxError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "data after the end of the compressed body",
+    )
+    .into()
+}
@@ -0,0 +1,333 @@
+use super::{LzhamBody, ENCODING};
+use crate::{CompressionOptions, DecompressionOptions};
+use http::{
+    header::{
+        HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE,
+        CONTENT_TYPE, VARY,
+    },
+    Method, Request, Response, StatusCode,
+};
+use http_body::Body;
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// The default minimum size of a compressed response.
+const DEFAULT_MIN_SIZE: u64 = 1024;
+
+/// The content types compressed by default.
+const DEFAULT_CONTENT_TYPES: &[&str] = &[
+    "text/",
+    "application/json",
+    "application/javascript",
+    "application/xml",
+    "image/svg+xml",
+];
+
+/// A [`Layer`] which applies the [`Lzham`] middleware to a service.
+///
+/// The configuration is shared by all the services created by the layer.
+#[derive(Clone, Debug)]
+pub struct LzhamLayer {
+    config: Arc<Config>,
+}
+
+#[derive(Clone, Debug)]
+struct Config {
+    compression: CompressionOptions,
+    decompression: DecompressionOptions,
+    min_size: u64,
+    content_types: Vec<String>,
+    max_request_size: Option<u64>,
+}
+
+impl LzhamLayer {
+    /// Creates a new layer with default options.
+    pub fn new() -> Self {
+        Self::with_options(
+            CompressionOptions::default(),
+            DecompressionOptions::default(),
+        )
+    }
+
+    /// Creates a new layer with provided [`compression`] and [`decompression`] options.
+    ///
+    /// The compression options apply to the responses, and the decompression options to the
+    /// requests, so they must match the ones used by the clients.
+    ///
+    /// [`compression`]: CompressionOptions
+    /// [`decompression`]: DecompressionOptions
+    pub fn with_options(
+        compression: CompressionOptions,
+        decompression: DecompressionOptions,
+    ) -> Self {
+        Self {
+            config: Arc::new(Config {
+                compression,
+                decompression,
+                min_size: DEFAULT_MIN_SIZE,
+                content_types: DEFAULT_CONTENT_TYPES.iter().map(|&t| t.into()).collect(),
+                max_request_size: None,
+            }),
+        }
+    }
+
+    /// Returns the minimum size of a compressed response body.
+    pub fn min_size(&self) -> u64 {
+        self.config.min_size
+    }
+
+    /// Sets the minimum size of a compressed response body.
+    ///
+    /// Responses whose size is known and smaller are sent uncompressed. It defaults to 1 KiB.
+    pub fn set_min_size(&mut self, min_size: u64) {
+        Arc::make_mut(&mut self.config).min_size = min_size;
+    }
+
+    /// Returns the content types of the compressed responses.
+    pub fn content_types(&self) -> &[String] {
+        &self.config.content_types
+    }
+
+    /// Sets the content types of the compressed responses.
+    ///
+    /// A content type matches if it starts with one of the entries, ignoring case, so
+    /// `text/` matches all the text types. Responses without a content type are only
+    /// compressed if the list is empty, which matches all the types. It defaults to the text
+    /// types, JSON, JavaScript, XML and SVG.
+    pub fn set_content_types<I, T>(&mut self, content_types: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<String>,
+    {
+        Arc::make_mut(&mut self.config).content_types =
+            content_types.into_iter().map(Into::into).collect();
+    }
+
+    /// Returns the maximum size of a decompressed request body.
+    pub fn max_request_size(&self) -> Option<u64> {
+        self.config.max_request_size
+    }
+
+    /// Sets the maximum size of a decompressed request body.
+    ///
+    /// Reading a larger request body fails with an error. It defaults to no limit.
+    pub fn set_max_request_size(&mut self, max_request_size: Option<u64>) {
+        Arc::make_mut(&mut self.config).max_request_size = max_request_size;
+    }
+}
+
+impl Default for LzhamLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S> Layer<S> for LzhamLayer {
+    type Service = Lzham<S>;
+
+    fn layer(&self, inner: S) -> Lzham<S> {
+        Lzham {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+/// A middleware for the `lzham` content coding, created by [`LzhamLayer`].
+///
+/// Request bodies with `Content-Encoding: lzham` are decompressed, and the `Content-Encoding`
+/// and `Content-Length` headers are removed. Response bodies are compressed when the request
+/// accepts `lzham`, the response is not already encoded, its content type matches and its
+/// size is not known to be below the minimum. Compressed responses get
+/// `Content-Encoding: lzham` and lose their `Content-Length`. Responses which are compressed
+/// or only refused by the request get `Vary: accept-encoding`.
+#[derive(Clone, Debug)]
+pub struct Lzham<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+impl<S> Lzham<S> {
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes the middleware and returns the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Lzham<S>
+where
+    S: Service<Request<LzhamBody<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Body,
+    ResBody: Body,
+{
+    type Response = Response<LzhamBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let accepts = req.method() != Method::HEAD && accepts_lzham(req.headers());
+
+        let (mut parts, body) = req.into_parts();
+        let body = if is_lzham(parts.headers.get(CONTENT_ENCODING)) {
+            parts.headers.remove(CONTENT_ENCODING);
+            parts.headers.remove(CONTENT_LENGTH);
+
+            LzhamBody::decompress(
+                body,
+                self.config.decompression.clone(),
+                self.config.max_request_size,
+            )
+        } else {
+            LzhamBody::identity(body)
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(Request::from_parts(parts, body)),
+            accepts,
+            config: self.config.clone(),
+        }
+    }
+}
+
+pin_project! {
+    /// The response future of [`Lzham`].
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        accepts: bool,
+        config: Arc<Config>,
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: Body,
+{
+    type Output = Result<Response<LzhamBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let res = ready!(this.inner.poll(cx))?;
+
+        if !this.config.compresses(&res) {
+            return Poll::Ready(Ok(res.map(LzhamBody::identity)));
+        }
+
+        // The response depends on the `Accept-Encoding` header even if it is not compressed.
+        let (mut parts, body) = res.into_parts();
+        parts
+            .headers
+            .append(VARY, HeaderValue::from_static("accept-encoding"));
+
+        if !*this.accepts {
+            let body = LzhamBody::identity(body);
+            return Poll::Ready(Ok(Response::from_parts(parts, body)));
+        }
+
+        parts.headers.remove(CONTENT_LENGTH);
+        parts
+            .headers
+            .insert(CONTENT_ENCODING, HeaderValue::from_static(ENCODING));
+
+        let body = LzhamBody::compress(body, this.config.compression.clone());
+
+        Poll::Ready(Ok(Response::from_parts(parts, body)))
+    }
+}
+
+impl Config {
+    /// Returns whether the response is compressed.
+    fn compresses<B: Body>(&self, res: &Response<B>) -> bool {
+        let headers = res.headers();
+
+        if matches!(
+            res.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+        ) || headers.contains_key(CONTENT_ENCODING)
+            || headers.contains_key(CONTENT_RANGE)
+        {
+            return false;
+        }
+
+        let size = headers
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok()?.parse().ok())
+            .or_else(|| res.body().size_hint().exact());
+        if matches!(size, Some(size) if size < self.min_size) {
+            return false;
+        }
+
+        if self.content_types.is_empty() {
+            return true;
+        }
+
+        match headers.get(CONTENT_TYPE).and_then(|t| t.to_str().ok()) {
+            Some(content_type) => self.content_types.iter().any(|prefix| {
+                content_type.len() >= prefix.len()
+                    && content_type.as_bytes()[..prefix.len()]
+                        .eq_ignore_ascii_case(prefix.as_bytes())
+            }),
+            None => false,
+        }
+    }
+}
+
+/// Returns whether the `Accept-Encoding` headers accept `lzham`, either by name or through
+/// `*`, with a non-zero quality.
+fn accepts_lzham(headers: &HeaderMap) -> bool {
+    let mut wildcard = false;
+
+    for coding in headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+    {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let accepted = params
+            .filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                Some(value).filter(|_| name.trim().eq_ignore_ascii_case("q"))
+            })
+            .all(|q| matches!(q.trim().parse::<f32>(), Ok(q) if q > 0.0));
+
+        if name.eq_ignore_ascii_case(ENCODING) {
+            return accepted;
+        } else if name == "*" {
+            wildcard = accepted;
+        }
+    }
+
+    wildcard
+}
+
+/// Returns whether the `Content-Encoding` header is exactly `lzham`.
+fn is_lzham(value: Option<&HeaderValue>) -> bool {
+    match value.and_then(|value| value.to_str().ok()) {
+        Some(value) => value.trim().eq_ignore_ascii_case(ENCODING),
+        None => false,
+    }
+}
@@ -24,21 +24,63 @@
 //!
 //! [`lzham_codec`]: https://github.com/richgel999/lzham_codec
 
+pub mod archive;
 pub mod compress;
 pub mod decompress;
+pub mod delta;
+pub mod detect;
+pub mod dictionary;
+mod error;
+pub mod frame;
+#[cfg(feature = "http")]
+pub mod http;
 mod low;
+pub mod memory;
+pub mod message;
+pub mod multi;
+pub mod pool;
+pub mod progress;
+pub mod recovery;
+pub mod report;
+pub mod stream;
+#[cfg(feature = "tar")]
+pub mod tar;
+#[cfg(feature = "tokio")]
+pub mod tokio;
 
 #[doc(inline)]
-pub use compress::{compress, compress_with_options, CompressionOptions};
+pub use compress::{
+    compress, compress_bound, compress_into, compress_into_with_options, compress_to_vec,
+    compress_to_vec_with_report, compress_with_options, compress_with_report, CompressionOptions,
+};
 #[doc(inline)]
-pub use decompress::{decompress, decompress_with_options, DecompressionOptions};
+pub use decompress::{
+    decompress, decompress_into, decompress_into_with_options, decompress_into_with_report,
+    decompress_to_vec, decompress_to_vec_with_report, decompress_with_options,
+    decompress_with_report, DecompressedSize, DecompressionOptions,
+};
+pub use error::{DecompressionFailure, Error, FailureCause, Result};
 #[doc(inline)]
 pub use low::{TableUpdateInterval, TableUpdateRate};
 
 mod test {
+    /// Returns `len` bytes of incompressible data, generated by a xorshift from `seed`.
+    #[cfg(test)]
+    fn noise(mut seed: u32, len: usize) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
     #[test]
     fn test_compress_and_decompress() {
         use crate::{compress, decompress};
+        use std::io::BufReader;
 
         let data = String::from(
             "This is a test.This is a test.This is a test.\
@@ -54,5 +96,1610 @@ mod test {
         let status = decompress(&mut comp.as_slice(), &mut decomp, data.len());
 
         assert!(status.is_success());
+        assert_eq!(decomp, data.as_bytes());
+
+        // The output may be full before the end of the stream is read.
+        for capacity in [1, 2, 7, 16] {
+            let mut reader = BufReader::with_capacity(capacity, comp.as_slice());
+            let mut decomp = Vec::new();
+            let status = decompress(&mut reader, &mut decomp, data.len());
+
+            assert!(status.is_success(), "capacity {}: {:?}", capacity, status);
+            assert_eq!(decomp, data.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_decompressor_reinit() {
+        use crate::{
+            compress_to_vec, low::decompression::Decompressor, CompressionOptions,
+            DecompressionOptions,
+        };
+
+        let data = b"This is a test.1234567".repeat(1 << 17);
+        let comp = compress_to_vec(
+            &data,
+            &CompressionOptions {
+                dict_size_log2: 22,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        // The state of a small dictionary must not be reused for a larger one.
+        let mut decompressor = Decompressor::from_options(DecompressionOptions {
+            dict_size_log2: 15,
+            ..Default::default()
+        });
+        assert!(decompressor.reinit(DecompressionOptions {
+            dict_size_log2: 22,
+            ..Default::default()
+        }));
+
+        let mut decomp = Vec::new();
+        assert!(decompressor
+            .decompress_to_vec(&comp, &mut decomp, data.len())
+            .is_success());
+        assert_eq!(decomp, data);
+    }
+
+    #[test]
+    fn test_memory_stats() {
+        use crate::{compress, memory};
+
+        let data = b"This is a test.This is a test.This is a test.";
+
+        let mut comp = Vec::new();
+        let status = compress(&mut &data[..], &mut comp);
+
+        assert!(status.is_success());
+
+        let stats = memory::stats();
+
+        assert!(stats.allocations > 0);
+        assert!(stats.peak_bytes > 0);
+        assert!(stats.peak_bytes >= stats.live_bytes);
+    }
+
+    #[test]
+    fn test_compress_into_and_decompress_into() {
+        use crate::{compress_into, decompress_into, Error};
+
+        let data = b"This is a test.This is a test.This is a test.1234567This is a test.";
+
+        let mut comp = [0; 256];
+        let comp_len = compress_into(data, &mut comp).unwrap();
+
+        match compress_into(data, &mut comp[..comp_len - 1]) {
+            Err(Error::OutputBufferTooSmall(Some(size))) => assert_eq!(size, comp_len),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let mut decomp = [0; 256];
+        let decomp_len = decompress_into(&comp[..comp_len], &mut decomp).unwrap();
+
+        assert_eq!(&decomp[..decomp_len], &data[..]);
+
+        match decompress_into(&comp[..comp_len], &mut decomp[..10]) {
+            Err(Error::OutputBufferTooSmall(None)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_compress_bound() {
+        use crate::{compress_bound, compress_into_with_options, CompressionOptions};
+
+        let data = noise(0x9E37_79B9, 300_000);
+
+        for &dict_size_log2 in &[15, 18, 26] {
+            let options = CompressionOptions {
+                dict_size_log2,
+                ..Default::default()
+            };
+
+            for &len in &[0, 1, 4096, 4097, data.len()] {
+                let mut comp = vec![0; compress_bound(len, &options)];
+                let comp_len =
+                    compress_into_with_options(&data[..len], &mut comp, &options).unwrap();
+
+                assert!(comp_len > len);
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_to_vec_and_decompress_to_vec() {
+        use crate::{
+            compress_to_vec, decompress_to_vec, CompressionOptions, DecompressedSize,
+            DecompressionOptions, Error,
+        };
+
+        let data: Vec<u8> = (0..200_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 11) as u8)
+            .collect();
+
+        let comp = compress_to_vec(&data, &CompressionOptions::default()).unwrap();
+
+        let options = DecompressionOptions::default();
+        for size in [
+            DecompressedSize::Exact(data.len()),
+            DecompressedSize::Limit(data.len()),
+            DecompressedSize::Unknown,
+        ] {
+            assert_eq!(decompress_to_vec(&comp, &options, size).unwrap(), data);
+        }
+
+        match decompress_to_vec(&comp, &options, DecompressedSize::Limit(data.len() - 1)) {
+            Err(Error::OutputBufferTooSmall(None)) => {}
+            res => panic!("unexpected result: {:?}", res.map(|v| v.len())),
+        }
+
+        match decompress_to_vec(&comp, &options, DecompressedSize::Exact(data.len() + 1)) {
+            Err(Error::SizeMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (data.len() + 1, data.len()))
+            }
+            res => panic!("unexpected result: {:?}", res.map(|v| v.len())),
+        }
+    }
+
+    #[test]
+    fn test_pools() {
+        use crate::{
+            compress::CompressionLevel,
+            pool::{CompressorPool, DecompressorPool},
+            CompressionOptions, DecompressionOptions,
+        };
+
+        let data = b"This is a test.This is a test.This is a test.1234567This is a test.";
+
+        let comp_pool = CompressorPool::new(1);
+        let decomp_pool = DecompressorPool::new(1);
+        let fast = CompressionOptions {
+            compression_level: CompressionLevel::Fastest,
+            ..Default::default()
+        };
+
+        for _ in 0..3 {
+            let mut comp = Vec::new();
+            assert!(comp_pool
+                .get(&fast)
+                .compress_to_vec(data, &mut comp)
+                .is_success());
+
+            let mut decomp = Vec::new();
+            let status = decomp_pool
+                .get(&DecompressionOptions::default())
+                .decompress_to_vec(&comp, &mut decomp, data.len());
+
+            assert!(status.is_success());
+            assert_eq!(&decomp[..], &data[..]);
+        }
+
+        let stats = comp_pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.idle), (2, 1, 1));
+
+        // Only one idle state is kept.
+        let first = comp_pool.get(&fast);
+        let second = comp_pool.get(&CompressionOptions::default());
+        drop((first, second));
+
+        let stats = comp_pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.idle), (3, 2, 1));
+        assert_eq!(decomp_pool.stats().hits, 2);
+    }
+
+    #[test]
+    fn test_progress_and_cancellation() {
+        use crate::{
+            compress::Compressor, decompress::Decompressor, progress::CancellationToken,
+            CompressionOptions, DecompressionOptions, Error,
+        };
+        use std::io::{self, Read};
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let token = CancellationToken::new();
+
+        let mut compressor = Compressor::from_options(CompressionOptions::default());
+        let mut comp = Vec::new();
+        let mut last = None;
+        compressor
+            .compress_with_progress(&mut data.as_slice(), &mut comp, &token, |p| last = Some(*p))
+            .unwrap();
+
+        let last = last.unwrap();
+        assert_eq!(last.input_consumed, data.len() as u64);
+        assert_eq!(last.output_produced, comp.len() as u64);
+
+        let mut decompressor = Decompressor::from_options(DecompressionOptions::default());
+        let mut decomp = Vec::new();
+        let mut calls = 0;
+        let result = decompressor.decompress_with_progress(
+            &mut comp.as_slice(),
+            &mut decomp,
+            data.len(),
+            &token,
+            |p| {
+                calls += 1;
+                if p.output_produced > 0 {
+                    token.cancel();
+                }
+            },
+        );
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(calls > 0);
+        assert!(decomp.len() < data.len());
+
+        let mut comp = Vec::new();
+        let result =
+            compressor.compress_with_progress(&mut data.as_slice(), &mut comp, &token, |_| {});
+
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert!(comp.is_empty());
+
+        // A cancellation stops readers rather than being retried.
+        struct Cancelling<'a> {
+            compressor: &'a mut Compressor,
+            token: &'a CancellationToken,
+        }
+
+        impl Read for Cancelling<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let mut comp = Vec::new();
+                self.compressor.compress_with_progress(
+                    &mut &b"data"[..],
+                    &mut comp,
+                    self.token,
+                    |_| {},
+                )?;
+                let len = comp.len().min(buf.len());
+                buf[..len].copy_from_slice(&comp[..len]);
+
+                Ok(len)
+            }
+        }
+
+        let mut reader = Cancelling {
+            compressor: &mut compressor,
+            token: &token,
+        };
+        let error = reader.read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Other);
+        assert_eq!(error.to_string(), "operation cancelled");
+    }
+
+    #[test]
+    fn test_reports() {
+        use crate::{
+            compress_to_vec_with_report, compress_with_report, decompress_to_vec_with_report,
+            decompress_with_report, CompressionOptions, DecompressedSize, DecompressionOptions,
+            TableUpdateInterval, TableUpdateRate,
+        };
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let comp_options = CompressionOptions {
+            table_update_rate: TableUpdateRate::FAST,
+            ..Default::default()
+        };
+        let decomp_options = DecompressionOptions {
+            table_update_rate: TableUpdateRate::FAST,
+            ..Default::default()
+        };
+
+        let mut comp = Vec::new();
+        let report = compress_with_report(&mut data.as_slice(), &mut comp, &comp_options).unwrap();
+
+        assert_eq!(report.bytes_in, data.len() as u64);
+        assert_eq!(report.bytes_out, comp.len() as u64);
+        assert!(report.iterations > 0);
+        assert_eq!(report.buffer_growths, 0);
+        assert!(report.ratio() < 1.0);
+        // Without an interval, the codec uses the default one whatever the rate.
+        assert_eq!(
+            report.params.table_update_interval,
+            TableUpdateInterval::default()
+        );
+
+        let (comp_vec, report) = compress_to_vec_with_report(&data, &comp_options).unwrap();
+
+        assert_eq!(comp_vec, comp);
+        assert_eq!(report.bytes_out, comp.len() as u64);
+
+        let mut decomp = Vec::new();
+        let report = decompress_with_report(
+            &mut comp.as_slice(),
+            &mut decomp,
+            data.len(),
+            &decomp_options,
+        )
+        .unwrap();
+
+        assert_eq!(decomp, data);
+        assert_eq!(report.bytes_in, comp.len() as u64);
+        assert_eq!(report.bytes_out, data.len() as u64);
+
+        let (decomp, report) =
+            decompress_to_vec_with_report(&comp, &decomp_options, DecompressedSize::Unknown)
+                .unwrap();
+
+        assert_eq!(decomp, data);
+        assert!(report.buffer_growths > 0);
+        assert_eq!(report.ratio(), comp.len() as f64 / data.len() as f64);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn test_tokio_encoder_and_decoder() {
+        use crate::tokio::{LzhamDecoder, LzhamEncoder};
+        use tokio::io::{self, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let data: Vec<u8> = (0..1_000_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 13) as u8)
+            .collect();
+
+        // The small pipe forces the encoder to wait for the decoder.
+        let (writer, reader) = io::duplex(1024);
+
+        let write = async {
+            let mut encoder = LzhamEncoder::new(writer);
+            for chunk in data.chunks(100_000) {
+                encoder.write_all(chunk).await.unwrap();
+                encoder.flush().await.unwrap();
+            }
+            encoder.shutdown().await.unwrap();
+        };
+
+        let read = async {
+            let mut decoder = LzhamDecoder::new(BufReader::new(reader));
+            let mut decomp = Vec::new();
+            decoder.read_to_end(&mut decomp).await.unwrap();
+
+            decomp
+        };
+
+        let ((), decomp) = tokio::join!(write, read);
+        assert_eq!(decomp, data);
+
+        // After a flush, everything written so far is decodable, even if the codec had
+        // pending output when the flush was requested.
+        let data = noise(0x9E37_79B9, 524_288);
+
+        let mut encoder = LzhamEncoder::new(Vec::new());
+        encoder.write_all(&data).await.unwrap();
+        encoder.flush().await.unwrap();
+
+        let comp = encoder.into_inner();
+        let mut decompressor = crate::decompress::Decompressor::from_options(Default::default());
+        let mut decomp = vec![0; data.len() + 1];
+        let (_, read, written) = decompressor.decompress_buf(&comp, &mut decomp, false);
+
+        assert_eq!(read, comp.len());
+        assert_eq!(&decomp[..written], &data[..]);
+
+        let mut decoder = LzhamDecoder::new(&[0; 16][..]);
+        let err = decoder.read_to_end(&mut Vec::new()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A truncated stream ends with an error rather than garbage.
+        let data = b"This is a test. ".repeat(10_000);
+        let comp = crate::compress_to_vec(&data, &Default::default()).unwrap();
+        let mut decoder = LzhamDecoder::new(&comp[..comp.len() / 2]);
+        let mut decomp = Vec::new();
+        let err = decoder.read_to_end(&mut decomp).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(decomp.len() < data.len());
+    }
+
+    #[cfg(feature = "tokio-util")]
+    #[test]
+    fn test_tokio_codec() {
+        use crate::{tokio::codec::LzhamCodec, CompressionOptions, DecompressionOptions};
+        use bytes::{Bytes, BytesMut};
+        use tokio_util::codec::{Decoder, Encoder};
+
+        let seed = b"This is a seed dictionary.".to_vec();
+        let mut codec = LzhamCodec::with_options(
+            CompressionOptions {
+                p_seed_bytes: Some(seed.clone()),
+                ..Default::default()
+            },
+            DecompressionOptions {
+                p_seed_bytes: Some(seed),
+                ..Default::default()
+            },
+        );
+        codec.set_max_frame_size(1000);
+
+        let frames = [
+            Bytes::from_static(b"This is a seed dictionary. This is a test."),
+            Bytes::new(),
+            Bytes::from(vec![7; 1000]),
+        ];
+
+        let mut buf = BytesMut::new();
+        for frame in &frames {
+            codec.encode(frame.clone(), &mut buf).unwrap();
+        }
+
+        assert!(codec.encode(Bytes::from(vec![0; 1001]), &mut buf).is_err());
+
+        // Frames are only decoded once they are complete.
+        let mut partial = buf.split_to(5);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+        let mut buf = partial;
+
+        for frame in &frames {
+            assert_eq!(codec.decode(&mut buf).unwrap().as_ref(), Some(frame));
+        }
+
+        assert!(codec.decode(&mut buf).unwrap().is_none());
+        assert!(buf.is_empty());
+
+        let mut large = BytesMut::from(&[0, 0, 0, 1, 0, 0, 4, 0][..]);
+        assert!(codec.decode(&mut large).is_err());
+    }
+
+    #[test]
+    fn test_message_mode() {
+        use crate::{
+            compress_to_vec,
+            message::{MessageCompressor, MessageDecompressor, MessageOptions},
+            Error,
+        };
+
+        let messages: Vec<Vec<u8>> = (0..8)
+            .map(|i| {
+                format!(
+                    "{{\"id\": {}, \"status\": \"ok\", \"items\": [1, 2, 3]}}",
+                    i
+                )
+                .into()
+            })
+            .collect();
+
+        let options = MessageOptions {
+            reset_interval: Some(4),
+            ..Default::default()
+        };
+        let mut compressor = MessageCompressor::new(Default::default(), options);
+        let mut decompressor = MessageDecompressor::new(Default::default(), options);
+
+        let mut comp = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            let mut buf = Vec::new();
+            compressor.compress_message(message, &mut buf).unwrap();
+
+            // Later messages match against the earlier ones.
+            if i % 4 != 0 {
+                let single = compress_to_vec(message, &Default::default()).unwrap();
+                assert!(buf.len() < single.len());
+            }
+
+            let mut decomp = Vec::new();
+            decompressor
+                .decompress_message(&buf, &mut decomp, message.len())
+                .unwrap();
+
+            assert_eq!(&decomp, message);
+            comp.push(buf);
+        }
+
+        // A corrupted message breaks the context until the next reset.
+        let mut decompressor = MessageDecompressor::new(Default::default(), options);
+        for (i, buf) in comp.iter().enumerate() {
+            let mut decomp = Vec::new();
+            let result = if i == 1 {
+                decompressor.decompress_message(&buf[..buf.len() / 2], &mut decomp, 1024)
+            } else {
+                decompressor.decompress_message(buf, &mut decomp, 1024)
+            };
+
+            match i {
+                0 | 4..=7 => assert_eq!(decomp, messages[i]),
+                _ => assert!(result.is_err() || decomp != messages[i]),
+            }
+        }
+
+        let mut decompressor = MessageDecompressor::new(Default::default(), options);
+        match decompressor.decompress_message(&comp[0], &mut Vec::new(), 10) {
+            Err(Error::OutputBufferTooSmall(None)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_recovery() {
+        use crate::recovery::{compress_with_flush_points, decompress_recovering};
+
+        let data: Vec<u8> = (0..1_000_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 13) as u8)
+            .collect();
+        let interval = 1 << 16;
+
+        let mut comp = Vec::new();
+        compress_with_flush_points(
+            &mut data.as_slice(),
+            &mut comp,
+            &Default::default(),
+            interval,
+        )
+        .unwrap();
+
+        let mut decomp = Vec::new();
+        let report = decompress_recovering(
+            &mut comp.as_slice(),
+            &mut decomp,
+            interval,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert!(report.finished);
+        assert!(report.damaged.is_empty());
+        assert_eq!(report.bytes_in, comp.len() as u64);
+        assert_eq!(decomp, data);
+
+        // The codec does not detect every corruption, some decode silently to wrong data.
+        let pos = comp.len() / 3;
+        let mut damaged = comp.clone();
+
+        for pattern in [0x5A, 0xFF] {
+            damaged.copy_from_slice(&comp);
+            for byte in &mut damaged[pos..pos + 16] {
+                *byte ^= pattern;
+            }
+
+            let mut decomp = Vec::new();
+            let report = decompress_recovering(
+                &mut damaged.as_slice(),
+                &mut decomp,
+                interval,
+                &Default::default(),
+            )
+            .unwrap();
+
+            assert!(report.finished);
+            assert_eq!(report.damaged.len(), 1);
+            assert!(report.damaged[0].compressed.contains(&(pos as u64)));
+
+            // Only the data between the flush points around the damage is missing.
+            let resumed = report.damaged[0].uncompressed_offset as usize;
+            assert_eq!(resumed % interval, 0);
+            assert_eq!(decomp.len(), data.len() - interval);
+            assert_eq!(&decomp[..resumed], &data[..resumed]);
+            assert_eq!(&decomp[resumed..], &data[resumed + interval..]);
+        }
+
+        // Without a flush point after the damage, the rest of the stream is lost.
+        let mut decomp = Vec::new();
+        let report = decompress_recovering(
+            &mut &damaged[..damaged.len() - 4000],
+            &mut decomp,
+            interval,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert!(!report.finished);
+        assert!(!report.damaged.is_empty());
+
+        // Incompressible data is stored verbatim, and may contain the bytes of the marker.
+        let mut data = noise(0x2545_F491, 200_000);
+        data[1000..1005].copy_from_slice(&[0x20, 0x00, 0x00, 0xFF, 0xFF]);
+
+        let mut comp = Vec::new();
+        compress_with_flush_points(
+            &mut data.as_slice(),
+            &mut comp,
+            &Default::default(),
+            interval,
+        )
+        .unwrap();
+
+        let mut decomp = Vec::new();
+        let report = decompress_recovering(
+            &mut comp.as_slice(),
+            &mut decomp,
+            interval,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert!(report.finished);
+        assert!(report.damaged.is_empty());
+        assert_eq!(decomp, data);
+    }
+
+    #[test]
+    fn test_decompression_failure() {
+        use crate::{
+            compress_to_vec, decompress_to_vec, decompress_with_report, CompressionOptions,
+            DecompressedSize, DecompressionOptions, Error, FailureCause,
+        };
+
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 9) as u8)
+            .collect();
+        let comp = compress_to_vec(&data, &CompressionOptions::default()).unwrap();
+
+        let failure = |result: crate::Result<_>| match result {
+            Err(Error::DecompressionFailure(failure)) => failure,
+            result => panic!("unexpected result: {:?}", result.map(|_: ()| ())),
+        };
+
+        // The codec only detects truncated raw blocks, which hold incompressible data.
+        let noise = noise(0x1234_5678, 100_000);
+        let noise_comp = compress_to_vec(&noise, &CompressionOptions::default()).unwrap();
+
+        let truncated = &noise_comp[..noise_comp.len() / 2];
+        let mut decomp = Vec::new();
+        let error = failure(
+            decompress_with_report(
+                &mut &truncated[..],
+                &mut decomp,
+                noise.len(),
+                &DecompressionOptions::default(),
+            )
+            .map(|_| ()),
+        );
+
+        assert_eq!(error.cause(), FailureCause::Truncated);
+        assert_eq!(error.compressed_offset, truncated.len() as u64);
+        assert_eq!(error.uncompressed_offset, decomp.len() as u64);
+        assert!(error.to_string().contains("at compressed offset"));
+
+        let mut damaged = comp.clone();
+        let pos = damaged.len() / 3;
+        for byte in &mut damaged[pos..pos + 16] {
+            *byte = 0;
+        }
+
+        let error = failure(
+            decompress_to_vec(
+                &damaged,
+                &Default::default(),
+                DecompressedSize::Limit(data.len()),
+            )
+            .map(|_| ()),
+        );
+
+        assert_eq!(error.cause(), FailureCause::Corrupted);
+        assert!(error.compressed_offset > pos as u64);
+        assert!(error.compressed_offset < comp.len() as u64);
+        assert_eq!(error.options, DecompressionOptions::default());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        use crate::{
+            compress_to_vec, decompress, decompress::Decompressor, decompress_into_with_report,
+            decompress_to_vec_with_report, decompress_with_report, DecompressedSize, Error,
+        };
+        use std::io::{BufReader, Read};
+
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        let comp = compress_to_vec(&data, &Default::default()).unwrap();
+
+        let trailer = b"more records";
+        let mut input = comp.clone();
+        input.extend_from_slice(trailer);
+
+        // A small buffer splits the end of the stream across reads.
+        let mut reader = BufReader::with_capacity(7, input.as_slice());
+        let mut decompressor = Decompressor::from_options(Default::default());
+        let mut decomp = Vec::new();
+        let status = decompressor.decompress(&mut reader, &mut decomp, data.len());
+
+        assert!(status.is_success());
+        assert_eq!(decomp, data);
+        assert_eq!(decompressor.total_in(), comp.len() as u64);
+        assert_eq!(decompressor.total_out(), data.len() as u64);
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, trailer);
+
+        let mut reader = input.as_slice();
+        let mut decomp = Vec::new();
+        let report =
+            decompress_with_report(&mut reader, &mut decomp, data.len(), &Default::default())
+                .unwrap();
+
+        assert_eq!(report.bytes_in, comp.len() as u64);
+        assert_eq!(reader, trailer);
+
+        let (decomp, report) =
+            decompress_to_vec_with_report(&input, &Default::default(), DecompressedSize::Unknown)
+                .unwrap();
+
+        assert_eq!(decomp, data);
+        assert_eq!(report.bytes_in, comp.len() as u64);
+
+        let mut decomp = vec![0; data.len()];
+        let report = decompress_into_with_report(&input, &mut decomp, &Default::default()).unwrap();
+
+        assert_eq!(decomp, data);
+        assert_eq!(
+            (report.bytes_in, report.bytes_out),
+            (comp.len() as u64, data.len() as u64)
+        );
+
+        match decompress_into_with_report(&input, &mut decomp[..10], &Default::default()) {
+            Err(Error::OutputBufferTooSmall(None)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let mut reader = input.as_slice();
+        let mut decomp = Vec::new();
+
+        assert!(decompress(&mut reader, &mut decomp, data.len()).is_success());
+        assert_eq!(reader, trailer);
+    }
+
+    #[test]
+    fn test_multi_member() {
+        use crate::{compress_to_vec, multi::decompress_multi, Error};
+
+        let chunks: Vec<Vec<u8>> = (0..3u32)
+            .map(|n| (0..20_000 * (n + 1)).map(|i| (i % (n + 7)) as u8).collect())
+            .collect();
+
+        let mut comp = Vec::new();
+        for chunk in &chunks {
+            comp.extend(compress_to_vec(chunk, &Default::default()).unwrap());
+        }
+
+        let mut decomp = Vec::new();
+        let report = decompress_multi(
+            &mut comp.as_slice(),
+            &mut decomp,
+            usize::MAX,
+            &Default::default(),
+        )
+        .unwrap();
+
+        assert_eq!(decomp, chunks.concat());
+        assert_eq!(report.members.len(), 3);
+        assert_eq!(report.bytes_in, comp.len() as u64);
+        assert_eq!(
+            report.members[1].compressed.start,
+            report.members[0].compressed.end
+        );
+        assert_eq!(report.members[2].uncompressed.end, decomp.len() as u64);
+
+        let mut decomp = Vec::new();
+        let report =
+            decompress_multi(&mut &[][..], &mut decomp, usize::MAX, &Default::default()).unwrap();
+
+        assert!(report.members.is_empty());
+
+        // Raw blocks of incompressible data detect a truncation.
+        let noise = noise(0x9E37_79B9, 10_000);
+        let noise_comp = compress_to_vec(&noise, &Default::default()).unwrap();
+        let truncated = [&comp[..], &noise_comp[..noise_comp.len() / 2]].concat();
+
+        let mut decomp = Vec::new();
+        match decompress_multi(
+            &mut truncated.as_slice(),
+            &mut decomp,
+            usize::MAX,
+            &Default::default(),
+        ) {
+            Err(Error::DecompressionFailure(failure)) => {
+                assert_eq!(failure.member, Some(3));
+                assert_eq!(failure.compressed_offset, truncated.len() as u64);
+                assert!(failure.to_string().contains("in member 3"));
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+
+        // Other errors are wrapped with the index of the stream.
+        let mut decomp = Vec::new();
+        let limit = chunks[0].len() + 1;
+        match decompress_multi(
+            &mut comp.as_slice(),
+            &mut decomp,
+            limit,
+            &Default::default(),
+        ) {
+            Err(Error::Member { member, error }) => {
+                assert_eq!(member, 1);
+                assert!(matches!(*error, Error::OutputBufferTooSmall(_)));
+            }
+            result => panic!("unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_frame() {
+        use crate::frame::{FrameReader, FrameWriter};
+        use std::io::{ErrorKind, Read, Write};
+
+        let data: Vec<u8> = (0..300_000u32)
+            .map(|i| (i % 251) as u8 ^ (i >> 11) as u8)
+            .collect();
+
+        let mut writer = FrameWriter::new(Vec::new());
+        writer.set_chunk_size(100_000);
+        for piece in data.chunks(7_777) {
+            writer.write_all(piece).unwrap();
+        }
+        writer.flush().unwrap();
+        let comp = writer.finish().unwrap();
+
+        assert!(comp.len() < data.len() / 10);
+
+        let mut decomp = Vec::new();
+        FrameReader::new(comp.as_slice())
+            .read_to_end(&mut decomp)
+            .unwrap();
+
+        assert_eq!(decomp, data);
+
+        // Incompressible data is stored, and streams can be appended.
+        let noise = noise(0x2545_F491, 50_000);
+
+        let mut writer = FrameWriter::new(comp.clone());
+        writer.write_all(&noise).unwrap();
+        let appended = writer.finish().unwrap();
+
+        assert!(appended.len() - comp.len() < noise.len() + 64);
+
+        let mut decomp = Vec::new();
+        FrameReader::new(appended.as_slice())
+            .read_to_end(&mut decomp)
+            .unwrap();
+
+        assert_eq!(decomp, [&data[..], &noise[..]].concat());
+
+        // Without the end chunk, the stream is truncated.
+        let truncated = &comp[..comp.len() - 13];
+        let error = FrameReader::new(truncated)
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        let mut damaged = appended.clone();
+        let pos = appended.len() - 1000;
+        damaged[pos] ^= 1;
+        let error = FrameReader::new(damaged.as_slice())
+            .read_to_end(&mut Vec::new())
+            .unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_delta() {
+        use crate::{
+            compress_to_vec,
+            delta::{diff, patch, PatchHeader},
+            Error,
+        };
+
+        let old = noise(0x2545_F491, 100_000);
+
+        let mut new = old.clone();
+        new[1000..1010].copy_from_slice(b"0123456789");
+        new.splice(50_000..50_000, b"This is a test.".iter().copied());
+        new.truncate(90_000);
+
+        let delta = diff(&old, &new).unwrap();
+
+        assert!(delta.len() < 1000);
+        assert!(compress_to_vec(&new, &Default::default()).unwrap().len() > 80_000);
+        assert_eq!(patch(&old, &delta).unwrap(), new);
+
+        let header = PatchHeader::read(&delta).unwrap();
+
+        assert_eq!(header.base_size, old.len() as u64);
+        assert_eq!(header.target_size, new.len() as u64);
+
+        match patch(&new, &delta) {
+            Err(Error::BaseMismatch { .. }) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match patch(&old, &new) {
+            Err(Error::InvalidFormat(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        // Without old data, the patch is a plain compression of the new data.
+        let delta = diff(&[], &new).unwrap();
+
+        assert_eq!(patch(&[], &delta).unwrap(), new);
+    }
+
+    #[test]
+    fn test_detect() {
+        use crate::{
+            archive::ArchiveWriter,
+            compress::CompressionFlag,
+            compress_to_vec,
+            delta::{diff, PatchHeader},
+            detect::{auto_decompress, detect, Format},
+            dictionary::DictionaryRegistry,
+            frame::FrameWriter,
+            CompressionOptions, DecompressionOptions,
+        };
+        use std::io::{BufReader, Cursor, ErrorKind, Read, Write};
+
+        let data = b"This is a test. ".repeat(1000);
+        let decompress = |comp: &[u8], options: &DecompressionOptions| {
+            // The small buffer splits the header from the rest of the stream.
+            let mut decoder = auto_decompress(BufReader::with_capacity(7, comp), options)?;
+            let mut decomp = Vec::new();
+            decoder.read_to_end(&mut decomp)?;
+
+            std::io::Result::Ok((decoder.format(), decomp))
+        };
+
+        let comp = compress_to_vec(&data, &Default::default()).unwrap();
+        assert_eq!(detect(&comp), Format::Raw);
+        assert_eq!(
+            decompress(&comp, &Default::default()).unwrap(),
+            (Format::Raw, data.clone())
+        );
+
+        // The dictionary size is taken from the zlib header.
+        let zlib = CompressionOptions {
+            dict_size_log2: 20,
+            compression_flags: Some(CompressionFlag::WriteZlibStream),
+            ..Default::default()
+        };
+        let comp = compress_to_vec(&data, &zlib).unwrap();
+        let format = Format::Zlib {
+            dict_size_log2: 20,
+            seed_adler32: None,
+        };
+        assert_eq!(detect(&comp), format);
+        assert_eq!(
+            decompress(&comp, &Default::default()).unwrap(),
+            (format, data.clone())
+        );
+
+        let seed = b"This is a seed.".to_vec();
+        let comp = compress_to_vec(
+            &data,
+            &CompressionOptions {
+                p_seed_bytes: Some(seed.clone()),
+                ..zlib
+            },
+        )
+        .unwrap();
+        assert!(matches!(
+            detect(&comp),
+            Format::Zlib {
+                dict_size_log2: 20,
+                seed_adler32: Some(_),
+            }
+        ));
+        let options = DecompressionOptions {
+            p_seed_bytes: Some(seed),
+            ..Default::default()
+        };
+        assert_eq!(decompress(&comp, &options).unwrap().1, data);
+
+        let mut writer = FrameWriter::new(Vec::new());
+        writer.write_all(&data).unwrap();
+        let comp = writer.finish().unwrap();
+        assert_eq!(detect(&comp), Format::Framed);
+        assert_eq!(
+            decompress(&comp, &Default::default()).unwrap(),
+            (Format::Framed, data.clone())
+        );
+
+        // The other formats are detected, but need more than a reader.
+        let mut registry = DictionaryRegistry::new();
+        let id = registry.insert(b"This is a dictionary.".to_vec());
+        let dictionary = registry
+            .compress_to_vec(id, &data, &Default::default())
+            .unwrap();
+        let patch = diff(b"This is a test.", &data).unwrap();
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add("test", &data).unwrap();
+        let archive = writer.finish().unwrap().into_inner();
+
+        assert_eq!(detect(&dictionary), Format::Dictionary(id));
+        assert_eq!(
+            detect(&patch),
+            Format::Patch(PatchHeader::read(&patch).unwrap())
+        );
+        assert_eq!(detect(&archive), Format::Archive);
+
+        for comp in [&dictionary, &patch, &archive] {
+            let error = decompress(comp, &Default::default()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+        }
+
+        // The last three have a flush type of 3, a wrong check of the raw block length, and
+        // no padding after the end of stream.
+        for comp in [
+            &b""[..],
+            b"\x00\x01\x02",
+            b"LZHM\x01",
+            b"LZHF\x02",
+            b"p",
+            b"\x80\x00\x02\x40\x00",
+            b"\xc1",
+        ] {
+            assert_eq!(detect(comp), Format::Unknown);
+
+            let error = decompress(comp, &Default::default()).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(error.to_string(), "unknown format");
+        }
+
+        // Raw streams are only recognized by the header of their first block, so other data
+        // may look like one until it is decompressed.
+        let text = b"This is not compressed.";
+        assert_eq!(detect(text), Format::Raw);
+
+        let error = decompress(text, &Default::default()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "unknown format");
+    }
+
+    #[test]
+    fn test_dictionary_registry() {
+        use crate::{
+            compress_to_vec,
+            dictionary::{DictionaryId, DictionaryRegistry},
+            DecompressedSize, Error,
+        };
+
+        let json = br#"{"id": 1, "name": "This is a test.", "tags": ["test", "example"]}"#;
+        let xml = br#"<item id="1"><name>This is a test.</name><tag>test</tag></item>"#;
+
+        let mut registry = DictionaryRegistry::new();
+        let json_id = registry.insert(json.to_vec());
+        let xml_id = registry.insert(xml.to_vec());
+
+        assert_ne!(json_id, xml_id);
+        assert_eq!(registry.insert(json.to_vec()), json_id);
+        assert_eq!(registry.len(), 2);
+        assert_eq!(json_id, DictionaryId::of(json));
+
+        let message = br#"{"id": 2, "name": "This is another test.", "tags": ["test"]}"#;
+        let comp = registry
+            .compress_to_vec(json_id, message, &Default::default())
+            .unwrap();
+
+        assert_eq!(DictionaryId::read(&comp).unwrap(), json_id);
+        assert!(comp.len() < compress_to_vec(message, &Default::default()).unwrap().len());
+
+        let decomp = registry
+            .decompress_to_vec(&comp, &Default::default(), DecompressedSize::Limit(1024))
+            .unwrap();
+
+        assert_eq!(decomp, &message[..]);
+
+        let mut other = DictionaryRegistry::new();
+        other.insert(xml.to_vec());
+
+        match other.decompress_to_vec(&comp, &Default::default(), DecompressedSize::Limit(1024)) {
+            Err(Error::DictionaryNotFound(id)) => assert_eq!(id, json_id),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        match registry.decompress_to_vec(
+            message,
+            &Default::default(),
+            DecompressedSize::Limit(1024),
+        ) {
+            Err(Error::InvalidFormat(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_archive() {
+        use crate::{
+            archive::{ArchiveReader, ArchiveWriter},
+            compress::CompressionLevel,
+            low::crc32,
+            CompressionOptions, Error,
+        };
+        use std::{convert::TryInto, io::Cursor};
+
+        let fast = CompressionOptions {
+            dict_size_log2: 20,
+            compression_level: CompressionLevel::Fastest,
+            ..Default::default()
+        };
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer.add("a.txt", b"This is a test.").unwrap();
+        writer.add("empty", b"").unwrap();
+        writer
+            .add_with_options("b.txt", &b"This is another test.".repeat(100), &fast)
+            .unwrap();
+        let mut archive = writer.finish().unwrap();
+
+        let mut reader = ArchiveReader::new(&mut archive).unwrap();
+
+        assert_eq!(reader.entries().len(), 3);
+        assert_eq!(reader.entry("b.txt").unwrap().dict_size_log2, 20);
+        assert_eq!(
+            reader.read("b.txt").unwrap(),
+            b"This is another test.".repeat(100)
+        );
+        assert_eq!(reader.read("a.txt").unwrap(), b"This is a test.");
+        assert_eq!(reader.read("empty").unwrap(), b"");
+
+        match reader.read("c.txt") {
+            Err(Error::EntryNotFound(name)) => assert_eq!(name, "c.txt"),
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        let mut writer = ArchiveWriter::append(&mut archive).unwrap();
+        writer.add("c.txt", b"This is a new test.").unwrap();
+        writer.add("a.txt", b"This is a replaced test.").unwrap();
+        writer.finish().unwrap();
+
+        let mut reader = ArchiveReader::new(&mut archive).unwrap();
+
+        assert_eq!(reader.entries().len(), 5);
+        assert_eq!(reader.read("c.txt").unwrap(), b"This is a new test.");
+        assert_eq!(reader.read("a.txt").unwrap(), b"This is a replaced test.");
+        assert_eq!(reader.read_at(0).unwrap(), b"This is a test.");
+
+        let mut damaged = archive.into_inner();
+        damaged[10] ^= 1;
+
+        match ArchiveReader::new(Cursor::new(&damaged))
+            .unwrap()
+            .read_at(0)
+        {
+            Err(Error::InvalidFormat(_)) | Err(Error::DecompressionFailure(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+
+        damaged.pop();
+
+        match ArchiveReader::new(Cursor::new(&damaged)) {
+            Err(Error::InvalidFormat(_)) => {}
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+
+        // A decompressor for a small dictionary must not be reused for a larger one.
+        let small = CompressionOptions {
+            dict_size_log2: 15,
+            ..Default::default()
+        };
+        let large = CompressionOptions {
+            dict_size_log2: 22,
+            ..Default::default()
+        };
+        let data = b"This is a large test.".repeat(150_000);
+
+        let mut writer = ArchiveWriter::new(Cursor::new(Vec::new())).unwrap();
+        writer
+            .add_with_options("small", b"This is a test.", &small)
+            .unwrap();
+        writer.add_with_options("large", &data, &large).unwrap();
+        let mut archive = writer.finish().unwrap().into_inner();
+
+        let mut reader = ArchiveReader::new(Cursor::new(&archive)).unwrap();
+
+        assert_eq!(reader.read("small").unwrap(), b"This is a test.");
+        assert_eq!(reader.read("large").unwrap(), data);
+
+        // Dictionary sizes outside of the limits of the native codec are rejected.
+        let footer = archive.len() - 28;
+        let directory =
+            u64::from_be_bytes(archive[footer..footer + 8].try_into().unwrap()) as usize;
+        archive[directory + 2 + "small".len() + 28] = 30;
+        let crc = crc32(0, &archive[directory..footer]);
+        archive[footer + 20..footer + 24].copy_from_slice(&crc.to_be_bytes());
+
+        match ArchiveReader::new(Cursor::new(&archive)) {
+            Err(Error::InvalidFormat(_)) => {}
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_solid_archive() {
+        use crate::{
+            archive::{ArchiveReader, ArchiveWriter},
+            low::crc32,
+            Error,
+        };
+        use std::{collections::HashSet, convert::TryInto, io::Cursor};
+
+        let files: Vec<(String, Vec<u8>)> = (0..200)
+            .map(|i| {
+                let data = format!(r#"{{"id": {}, "name": "This is test {}."}}"#, i, i);
+                (format!("{}.json", i), data.into_bytes())
+            })
+            .collect();
+
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        for (name, data) in &files {
+            writer.add(name, data).unwrap();
+        }
+        let separate = writer.finish().unwrap();
+
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        writer.set_solid_block_size(Some(2048));
+        for (name, data) in &files {
+            writer.add(name, data).unwrap();
+        }
+        writer.finish_block().unwrap();
+        writer.add("big", &[0; 4096]).unwrap();
+        let solid = writer.finish().unwrap();
+
+        // The directory is larger than the data, so only the streams are compared.
+        let streams = |archive: Vec<u8>| -> u64 {
+            let reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+            let streams: HashSet<_> = reader
+                .entries()
+                .iter()
+                .map(|entry| (entry.offset, entry.compressed_size))
+                .collect();
+
+            streams.iter().map(|(_, size)| size).sum()
+        };
+
+        assert!(streams(solid.clone()) * 4 < streams(separate));
+
+        let mut reader = ArchiveReader::new(Cursor::new(solid)).unwrap();
+        let entries = reader.entries();
+
+        assert_eq!(entries.len(), 201);
+        assert!(entries.iter().all(|entry| entry.solid.is_some()));
+
+        let blocks: HashSet<_> = entries.iter().map(|entry| entry.offset).collect();
+        let big = reader.entry("big").unwrap().solid.unwrap();
+
+        assert!(blocks.len() > 2 && blocks.len() < 20);
+        assert_eq!((big.offset, big.block_size), (0, 4096));
+
+        assert_eq!(reader.read("123.json").unwrap(), files[123].1);
+        assert_eq!(reader.read("0.json").unwrap(), files[0].1);
+        assert_eq!(reader.read("1.json").unwrap(), files[1].1);
+        assert_eq!(reader.read("big").unwrap(), [0; 4096]);
+
+        for (i, (name, data)) in files.iter().enumerate() {
+            assert_eq!(&reader.entries()[i].name, name);
+            assert_eq!(&reader.read_at(i).unwrap(), data);
+        }
+
+        // An entry claiming a larger size for the cached block must not read past its end.
+        let mut writer = ArchiveWriter::new(Vec::new()).unwrap();
+        writer.set_solid_block_size(Some(2048));
+        writer.add("a", b"This is a test.").unwrap();
+        writer.add("b", b"This is b.").unwrap();
+        let mut archive = writer.finish().unwrap();
+
+        let footer = archive.len() - 28;
+        let directory =
+            u64::from_be_bytes(archive[footer..footer + 8].try_into().unwrap()) as usize;
+        let solid = directory + 2 * (1 + 42) + 16;
+        archive[solid..solid + 8].copy_from_slice(&25u64.to_be_bytes());
+        archive[solid + 8..solid + 16].copy_from_slice(&35u64.to_be_bytes());
+        let crc = crc32(0, &archive[directory..footer]);
+        archive[footer + 20..footer + 24].copy_from_slice(&crc.to_be_bytes());
+
+        let mut reader = ArchiveReader::new(Cursor::new(archive)).unwrap();
+
+        assert_eq!(reader.read("a").unwrap(), b"This is a test.");
+        match reader.read("b") {
+            Err(Error::InvalidFormat(_)) => {}
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_compressed_stream() {
+        use crate::stream::CompressedStream;
+        use std::{
+            io::{Read, Write},
+            net::{TcpListener, TcpStream},
+            thread,
+        };
+
+        // Requests are length-prefixed, and answered with their reverse.
+        fn exchange<S: Read + Write + Send + 'static>(client: S, server: S) {
+            let server = thread::spawn(move || {
+                let mut stream = CompressedStream::new(server);
+                let mut len = [0; 4];
+
+                loop {
+                    match stream.read(&mut len[..1]).unwrap() {
+                        0 => break,
+                        _ => stream.read_exact(&mut len[1..]).unwrap(),
+                    }
+
+                    let mut request = vec![0; u32::from_be_bytes(len) as usize];
+                    stream.read_exact(&mut request).unwrap();
+                    request.reverse();
+                    stream.write_all(&request).unwrap();
+                    stream.flush().unwrap();
+                }
+
+                stream.finish().unwrap();
+                stream.read_stats()
+            });
+
+            let mut stream = CompressedStream::new(client);
+            for i in 0..10 {
+                let request = format!("This is request {}. ", i).repeat(1000 * i + 1);
+                stream
+                    .write_all(&(request.len() as u32).to_be_bytes())
+                    .unwrap();
+                stream.write_all(request.as_bytes()).unwrap();
+                stream.flush().unwrap();
+
+                let mut response = vec![0; request.len()];
+                stream.read_exact(&mut response).unwrap();
+                response.reverse();
+                assert_eq!(response, request.as_bytes());
+            }
+
+            stream.finish().unwrap();
+            assert_eq!(stream.read(&mut [0; 16]).unwrap(), 0);
+
+            let server_stats = server.join().unwrap();
+            assert_eq!(server_stats, stream.write_stats());
+            assert!(stream.write_stats().ratio() < 0.1);
+            assert!(stream.read_stats().ratio() < 0.1);
+            assert!(stream.read_stats().uncompressed > 400_000);
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        exchange(client, server);
+
+        #[cfg(unix)]
+        {
+            let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+            exchange(client, server);
+        }
+    }
+
+    #[cfg(all(feature = "tar", unix))]
+    #[test]
+    fn test_tar() {
+        use crate::{compress_to_vec, tar};
+        use std::{
+            fs::{self, File},
+            io::ErrorKind,
+            os::unix::fs::{symlink, PermissionsExt},
+            time::{Duration, SystemTime},
+        };
+
+        let root = std::env::temp_dir().join(format!("lzham-test-tar-{}", std::process::id()));
+        let src = root.join("src");
+        let dest = root.join("dest");
+        let _ = fs::remove_dir_all(&root);
+
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), b"This is a test.".repeat(1000)).unwrap();
+        fs::write(src.join("sub/b.sh"), b"echo This is another test.").unwrap();
+        fs::set_permissions(src.join("sub/b.sh"), fs::Permissions::from_mode(0o750)).unwrap();
+        symlink("../a.txt", src.join("sub/link")).unwrap();
+
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        File::options()
+            .write(true)
+            .open(src.join("a.txt"))
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        let comp = tar::pack_dir(&src, Vec::new(), &Default::default()).unwrap();
+
+        assert!(comp.len() < 1000);
+
+        tar::unpack(comp.as_slice(), &dest).unwrap();
+
+        assert_eq!(
+            fs::read(dest.join("a.txt")).unwrap(),
+            b"This is a test.".repeat(1000)
+        );
+        assert_eq!(
+            fs::metadata(dest.join("a.txt"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            mtime
+        );
+        assert_eq!(
+            fs::metadata(dest.join("sub/b.sh"))
+                .unwrap()
+                .permissions()
+                .mode()
+                & 0o777,
+            0o750
+        );
+        assert_eq!(
+            fs::read_link(dest.join("sub/link")).unwrap(),
+            std::path::Path::new("../a.txt")
+        );
+
+        // The tar crate refuses to write such paths, so the header is filled by hand.
+        let evil = |entries: &[(&[u8], ::tar::EntryType, &[u8])]| {
+            let mut builder = ::tar::Builder::new(Vec::new());
+            for &(name, entry_type, link) in entries {
+                let mut header = ::tar::Header::new_old();
+                header.as_old_mut().name[..name.len()].copy_from_slice(name);
+                header.as_old_mut().linkname[..link.len()].copy_from_slice(link);
+                header.set_entry_type(entry_type);
+                header.set_size(0);
+                header.set_cksum();
+                builder.append(&header, &[][..]).unwrap();
+            }
+
+            compress_to_vec(&builder.into_inner().unwrap(), &Default::default()).unwrap()
+        };
+
+        for comp in [
+            evil(&[(b"../evil", ::tar::EntryType::Regular, b"")]),
+            evil(&[(b"/tmp/evil", ::tar::EntryType::Regular, b"")]),
+            evil(&[(b"link", ::tar::EntryType::Symlink, b"../evil")]),
+            evil(&[(b"link", ::tar::EntryType::Symlink, b"/etc/passwd")]),
+            evil(&[(b"link", ::tar::EntryType::Link, b"../evil")]),
+            // Each link stays inside on its own, but the second one is created through the
+            // first one.
+            evil(&[
+                (b"a/b", ::tar::EntryType::Symlink, b".."),
+                (b"a/b/c", ::tar::EntryType::Symlink, b".."),
+            ]),
+        ] {
+            let error = tar::unpack(comp.as_slice(), &dest).unwrap_err();
+
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().contains("outside"), "{}", error);
+        }
+
+        assert!(!root.join("evil").exists());
+        assert!(!dest.join("link").exists());
+        assert!(fs::symlink_metadata(dest.join("c")).is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    // The hyper client and server are only available to the tests.
+    #[cfg(all(test, feature = "http"))]
+    #[tokio::test]
+    async fn test_http_layer() {
+        use crate::{compress_to_vec, decompress_to_vec, http::LzhamLayer, DecompressedSize};
+        use bytes::Bytes;
+        use http_body_util::{BodyExt, Full};
+        use hyper::{client::conn::http1 as client, server::conn::http1 as server};
+        use hyper_util::{rt::TokioIo, service::TowerToHyperService};
+        use std::{
+            convert::Infallible,
+            future::Future,
+            pin::Pin,
+            task::{Context, Poll},
+        };
+        use tokio::net::{TcpListener, TcpStream};
+        use tower_layer::Layer;
+        use tower_service::Service;
+
+        // Echoes the request body, as text under `/text`.
+        #[derive(Clone)]
+        struct Echo;
+
+        impl<B> Service<hyper::Request<B>> for Echo
+        where
+            B: hyper::body::Body + Send + 'static,
+            B::Data: Send,
+            B::Error: std::fmt::Display,
+        {
+            type Response = hyper::Response<Full<Bytes>>;
+            type Error = Infallible;
+            type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Infallible>> + Send>>;
+
+            fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, req: hyper::Request<B>) -> Self::Future {
+                Box::pin(async move {
+                    let content_type = match req.uri().path() {
+                        "/text" => "text/plain; charset=utf-8",
+                        _ => "application/octet-stream",
+                    };
+
+                    let res = match req.into_body().collect().await {
+                        Ok(body) => {
+                            let body = body.to_bytes();
+                            hyper::Response::builder()
+                                .header("content-type", content_type)
+                                .header("content-length", body.len())
+                                .body(Full::new(body))
+                        }
+                        Err(e) => hyper::Response::builder()
+                            .status(400)
+                            .body(Full::new(e.to_string().into())),
+                    };
+
+                    Ok(res.unwrap())
+                })
+            }
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut layer = LzhamLayer::new();
+        layer.set_max_request_size(Some(1_000_000));
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let service = TowerToHyperService::new(layer.layer(Echo));
+                tokio::spawn(
+                    server::Builder::new().serve_connection(TokioIo::new(stream), service),
+                );
+            }
+        });
+
+        let stream = TcpStream::connect(addr).await.unwrap();
+        let (mut sender, conn) = client::handshake(TokioIo::new(stream)).await.unwrap();
+        tokio::spawn(conn);
+
+        let mut send = |path: &str, accept: &str, encoding: Option<&str>, body: Vec<u8>| {
+            let mut req = hyper::Request::post(path).header("accept-encoding", accept);
+            if let Some(encoding) = encoding {
+                req = req.header("content-encoding", encoding);
+            }
+            let req = req.body(Full::<Bytes>::from(body)).unwrap();
+            let res = sender.send_request(req);
+
+            async move {
+                let (parts, body) = res.await.unwrap().into_parts();
+                let body = body.collect().await.unwrap().to_bytes().to_vec();
+
+                (parts, body)
+            }
+        };
+
+        let data = b"This is a test. ".repeat(10_000);
+        let comp = compress_to_vec(&data, &Default::default()).unwrap();
+
+        // The request is decompressed, and the response compressed.
+        let (parts, body) = send("/text", "gzip, lzham", Some("lzham"), comp.clone()).await;
+        assert_eq!(parts.status, 200);
+        assert_eq!(parts.headers["content-encoding"], "lzham");
+        assert_eq!(parts.headers["vary"], "accept-encoding");
+        assert!(!parts.headers.contains_key("content-length"));
+        assert!(body.len() < data.len() / 10);
+        assert_eq!(
+            decompress_to_vec(&body, &Default::default(), DecompressedSize::Unknown).unwrap(),
+            data
+        );
+
+        // Binary, small and refused responses are sent as they are. Refused ones would have
+        // been compressed with another `Accept-Encoding`.
+        for (path, accept, len, refused) in [
+            ("/binary", "lzham", data.len(), false),
+            ("/text", "lzham", 100, false),
+            ("/text", "lzham;q=0, *", data.len(), true),
+            ("/text", "LZHAM; Q=0", data.len(), true),
+            ("/text", "gzip", data.len(), true),
+        ] {
+            let (parts, body) = send(path, accept, None, data[..len].to_vec()).await;
+            assert_eq!(parts.status, 200);
+            assert!(!parts.headers.contains_key("content-encoding"));
+            assert_eq!(parts.headers.contains_key("vary"), refused);
+            assert_eq!(body, &data[..len]);
+        }
+
+        let (parts, _) = send("/text", "*", None, data.clone()).await;
+        assert_eq!(parts.headers["content-encoding"], "lzham");
+
+        // Truncated, damaged and oversized request bodies fail.
+        let large = compress_to_vec(&[0; 2_000_000], &Default::default()).unwrap();
+        for comp in [comp[..comp.len() / 2].to_vec(), vec![0; 16], large] {
+            let (parts, _) = send("/text", "identity", Some("lzham"), comp).await;
+            assert_eq!(parts.status, 400);
+        }
     }
 }
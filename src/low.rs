@@ -5,12 +5,28 @@ mod traits;
 use std::os::raw::c_uint;
 use traits::CType;
 
+/// The size of the intermediate buffers between the native codec and a reader or writer.
+pub(crate) const BUF_SIZE: usize = 1 << 16;
+
+/// The smallest base 2 log of the dictionary size supported by the native codec.
+pub(crate) const MIN_DICT_SIZE_LOG2: u32 = 15;
+/// The largest base 2 log of the dictionary size supported by the native codec.
+pub(crate) const MAX_DICT_SIZE_LOG2: u32 = 29;
+
+/// Returns the end of `seed` which fits in a dictionary of `dict_size_log2`, clamped to the
+/// limits of the native codec.
+pub(crate) fn seed_tail(seed: &[u8], dict_size_log2: u32) -> &[u8] {
+    let dict_size = 1usize << dict_size_log2.clamp(MIN_DICT_SIZE_LOG2, MAX_DICT_SIZE_LOG2);
+
+    &seed[seed.len().saturating_sub(dict_size)..]
+}
+
 /// The table update rate for compression/decompression.
 ///
 /// You can create this by using the [`new`] method or use one of the constants.
 ///
 /// [`new`]: TableUpdateRate::new()
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TableUpdateRate(pub(crate) u32);
 
 impl TableUpdateRate {
@@ -74,7 +90,7 @@ impl TableUpdateRate {
 /// The table update interval for compression/decompression.
 ///
 /// It stores the max and the slowest interval.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct TableUpdateInterval(pub u32, pub u32);
 
 impl TableUpdateInterval {
@@ -105,3 +121,14 @@ impl CType for TableUpdateInterval {
         (self.0 as c_uint, self.1 as c_uint)
     }
 }
+
+/// Updates the CRC-32 checksum `crc` with `data`. The initial checksum is `0`.
+pub(crate) fn crc32(crc: u32, data: &[u8]) -> u32 {
+    unsafe {
+        lzham_sys::lzham_z_crc32(
+            crc as lzham_sys::lzham_z_ulong,
+            data.as_ptr(),
+            data.len() as lzham_sys::size_t,
+        ) as u32
+    }
+}
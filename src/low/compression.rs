@@ -2,98 +2,296 @@ mod structures;
 
 pub use structures::*;
 
-use super::traits::CType;
-use lzham_sys::lzham_compress_state_ptr;
-use std::{
-    io::{BufRead, Write},
-    os::raw::c_uint,
+use super::{traits::CType, BUF_SIZE};
+use crate::{
+    memory,
+    progress::{CancellationToken, Progress, Tracker},
+    report::CompressionReport,
+    Error, Result,
 };
+use lzham_sys::lzham_compress_state_ptr;
+use std::io::{BufRead, Write};
+
+/// The minimum number of bytes reserved when a vector runs out of capacity.
+const MIN_VEC_GROWTH: usize = 1 << 12;
 
 /// A high level compressor.
-#[derive(Clone, Debug)]
-pub struct Compressor(lzham_compress_state_ptr);
+///
+/// The native state is released when the compressor is dropped.
+#[derive(Debug)]
+pub struct Compressor {
+    state: lzham_compress_state_ptr,
+    // The native state keeps pointing to the seed bytes, so the options must outlive it.
+    options: CompressionOptions,
+}
 
 impl Compressor {
     /// Creates a new [`Compressor`] from [`options`].
     ///
     /// [`options`]: CompressionOptions
     pub fn from_options(options: CompressionOptions) -> Self {
-        Self(unsafe { lzham_sys::lzham_compress_init(&options.to_c_type()) })
+        memory::install();
+
+        let state = unsafe { lzham_sys::lzham_compress_init(&(&options).to_c_type()) };
+
+        Self { state, options }
     }
 
-    /// Reinitializes the compressor.
-    pub fn reinit(&self) -> Self {
-        Self(unsafe { lzham_sys::lzham_compress_reinit(self.0) })
+    /// Returns the [`options`] the compressor was created with.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn options(&self) -> &CompressionOptions {
+        &self.options
     }
 
-    /// Deinitializes the compressor.
+    /// Reinitializes the compressor, so it can compress a new stream.
+    ///
+    /// It reuses the already allocated native state. Returns whether the reinitialization
+    /// succeeded.
+    pub fn reinit(&mut self) -> bool {
+        let state = unsafe { lzham_sys::lzham_compress_reinit(self.state) };
+
+        // On failure, the old state is still allocated and is released on drop.
+        if state.is_null() {
+            false
+        } else {
+            self.state = state;
+            true
+        }
+    }
+
+    /// Deinitializes the compressor and returns the adler32 of the source data.
     ///
     /// It cannot be used to compress further.
-    pub fn deinit(&self) -> u32 {
-        unsafe { lzham_sys::lzham_compress_deinit(self.0) }
+    pub fn deinit(mut self) -> u32 {
+        let state = std::mem::replace(&mut self.state, std::ptr::null_mut());
+
+        unsafe { lzham_sys::lzham_compress_deinit(state) }
+    }
+
+    /// Compresses as much of `input` into `output` as possible, according to `flush`.
+    ///
+    /// Returns the status, the number of bytes read from `input` and the number of bytes
+    /// written to `output`. `output` must not be empty.
+    ///
+    /// It can be called repeatedly to compress a stream incrementally. Once it is called with
+    /// [`CompressionFlush::Finish`], it must be called with it until it returns a success or
+    /// failure status.
+    pub fn compress_buf(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: CompressionFlush,
+    ) -> (CompressionStatus, usize, usize) {
+        if self.state.is_null() {
+            return (CompressionStatus::FailedInitialization, 0, 0);
+        }
+
+        let mut in_len = input.len() as lzham_sys::size_t;
+        let mut out_len = output.len() as lzham_sys::size_t;
+
+        let status_int = unsafe {
+            lzham_sys::lzham_compress2(
+                self.state,
+                input.as_ptr(),
+                &mut in_len,
+                output.as_mut_ptr(),
+                &mut out_len,
+                flush.to_c_type(),
+            )
+        };
+
+        (
+            CompressionStatus::from_c_type(status_int),
+            in_len as usize,
+            out_len as usize,
+        )
     }
 
     /// Compresses input data into the output buffer with already specified [`options`].
     ///
+    /// The input is read and the output is written incrementally.
+    ///
     /// [`options`]: CompressionOptions
     pub fn compress<R: BufRead, W: Write>(
-        &self,
+        &mut self,
         input: &mut R,
         output: &mut W,
     ) -> CompressionStatus {
-        let mut input_buf = Vec::new();
-        if input.read_to_end(&mut input_buf).is_err() {
-            return CompressionStatus::Failed;
-        }
+        self.compress_stream(input, output, None, &mut |_| {}, &mut Tracker::new())
+            .unwrap_or(CompressionStatus::Failed)
+    }
 
-        let mut output_buffer: Vec<u8> = Vec::with_capacity(2 << 12);
+    /// Compresses input data into the output buffer and returns a [`CompressionReport`].
+    ///
+    /// It fails with [`Error::Compression`] if the compression does not succeed.
+    pub fn compress_with_report<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<CompressionReport> {
+        let mut tracker = Tracker::new();
+        let status = self.compress_stream(input, output, None, &mut |_| {}, &mut tracker)?;
 
-        let mut in_buf_ofs = 0;
-        let mut out_buf_ofs = 0;
+        self.report(status, &tracker)
+    }
 
-        let mut status;
+    /// Compresses input data into the output buffer, reporting the progress.
+    ///
+    /// `progress` is called after every iteration of the native codec, and `cancel` is
+    /// checked before every iteration. If the token is cancelled, it stops and returns
+    /// [`Error::Cancelled`]. The compressor must then be [reinitialized] before it is used
+    /// again.
+    ///
+    /// [reinitialized]: Compressor::reinit
+    pub fn compress_with_progress<R, W, F>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        cancel: &CancellationToken,
+        mut progress: F,
+    ) -> Result<CompressionReport>
+    where
+        R: BufRead,
+        W: Write,
+        F: FnMut(&Progress),
+    {
+        let mut tracker = Tracker::new();
+        let status =
+            self.compress_stream(input, output, Some(cancel), &mut progress, &mut tracker)?;
 
-        let mut num_in_bytes;
-        let mut out_buf_len;
+        self.report(status, &tracker)
+    }
+
+    fn compress_stream<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        cancel: Option<&CancellationToken>,
+        progress: &mut dyn FnMut(&Progress),
+        tracker: &mut Tracker,
+    ) -> Result<CompressionStatus> {
+        let mut out_buf = [0; BUF_SIZE];
+        let mut flush = CompressionFlush::None;
 
         loop {
-            num_in_bytes = input_buf.len() as lzham_sys::size_t - in_buf_ofs;
-            out_buf_len = output_buffer.capacity() as lzham_sys::size_t - out_buf_ofs;
-
-            let status_int = unsafe {
-                lzham_sys::lzham_compress(
-                    self.0,
-                    input_buf.as_ptr().add(in_buf_ofs as usize),
-                    &mut num_in_bytes,
-                    output_buffer.as_mut_ptr().add(out_buf_ofs as usize),
-                    &mut out_buf_len,
-                    1 as c_uint,
-                )
-            };
-
-            unsafe {
-                output_buffer.set_len(out_buf_len as usize);
+            if matches!(cancel, Some(cancel) if cancel.is_cancelled()) {
+                return Err(Error::Cancelled);
+            }
+
+            let in_buf = input.fill_buf()?;
+            if in_buf.is_empty() {
+                flush = CompressionFlush::Finish;
             }
 
-            in_buf_ofs += num_in_bytes;
-            out_buf_ofs += out_buf_len;
+            let (status, read, written) = self.compress_buf(in_buf, &mut out_buf, flush);
 
-            status = CompressionStatus::from_c_type(status_int);
+            input.consume(read);
+            output.write_all(&out_buf[..written])?;
+            progress(tracker.update(read, written));
 
             if status.is_success_or_first_failure() {
-                break;
-            } else if let CompressionStatus::HasMoreOutput = status {
-                let size = output_buffer.len();
+                return Ok(status);
+            }
+        }
+    }
 
-                output_buffer.resize(size + (size >> 3) + 6, 0);
+    /// Compresses `input` and appends the compressed data to `output`.
+    ///
+    /// The data is compressed directly into the spare capacity of `output`, which grows as
+    /// needed.
+    pub fn compress_to_vec(&mut self, input: &[u8], output: &mut Vec<u8>) -> CompressionStatus {
+        self.compress_vec(input, output, &mut Tracker::new())
+    }
+
+    /// Compresses `input`, appends the compressed data to `output` and returns a
+    /// [`CompressionReport`].
+    ///
+    /// It fails with [`Error::Compression`] if the compression does not succeed.
+    pub fn compress_to_vec_with_report(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<CompressionReport> {
+        let mut tracker = Tracker::new();
+        let status = self.compress_vec(input, output, &mut tracker);
+
+        self.report(status, &tracker)
+    }
+
+    fn compress_vec(
+        &mut self,
+        mut input: &[u8],
+        output: &mut Vec<u8>,
+        tracker: &mut Tracker,
+    ) -> CompressionStatus {
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(MIN_VEC_GROWTH));
+                tracker.grow();
+            }
+
+            let len = output.len();
+            output.resize(output.capacity(), 0);
+
+            let (status, read, written) =
+                self.compress_buf(input, &mut output[len..], CompressionFlush::Finish);
+
+            output.truncate(len + written);
+            input = &input[read..];
+            tracker.update(read, written);
+
+            if status.is_success_or_first_failure() {
+                return status;
             }
         }
+    }
 
-        // Write contents of `out_buf` to user's `output` buffer
-        if output.write(&output_buffer).is_err() {
-            return CompressionStatus::Failed;
+    fn report(&self, status: CompressionStatus, tracker: &Tracker) -> Result<CompressionReport> {
+        match status {
+            CompressionStatus::Success => Ok(CompressionReport::new(tracker, &self.options)),
+            status => Err(Error::Compression(status)),
         }
+    }
+}
+
+// The native state is not tied to the thread that created it.
+unsafe impl Send for Compressor {}
 
-        status
+impl Drop for Compressor {
+    fn drop(&mut self) {
+        if !self.state.is_null() {
+            unsafe { lzham_sys::lzham_compress_deinit(self.state) };
+        }
     }
 }
+
+/// Compresses `input` into `output` in a single call to the native codec.
+///
+/// Returns the status and the size of the compressed data. If the status is
+/// [`OutputBufferTooSmall`], the size is the required size of `output`.
+///
+/// [`OutputBufferTooSmall`]: CompressionStatus::OutputBufferTooSmall
+pub(crate) fn compress_memory(
+    input: &[u8],
+    output: &mut [u8],
+    options: &CompressionOptions,
+) -> (CompressionStatus, usize) {
+    memory::install();
+
+    let mut out_len = output.len() as lzham_sys::size_t;
+    let mut adler32 = 0;
+
+    let status_int = unsafe {
+        lzham_sys::lzham_compress_memory(
+            &options.to_c_type(),
+            output.as_mut_ptr(),
+            &mut out_len,
+            input.as_ptr(),
+            input.len() as lzham_sys::size_t,
+            &mut adler32,
+        )
+    };
+
+    (CompressionStatus::from_c_type(status_int), out_len as usize)
+}
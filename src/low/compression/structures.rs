@@ -6,7 +6,7 @@ use std::{
 };
 
 /// Options to tweak compression.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct CompressionOptions {
     /// The base 2 log of the dictionary size.
     pub dict_size_log2: u32,
@@ -23,6 +23,8 @@ pub struct CompressionOptions {
     /// Currently unimplemented.
     pub compression_flags: Option<CompressionFlag>,
     /// Number of seed bytes to load.
+    ///
+    /// If not specified, all of [`p_seed_bytes`](Self::p_seed_bytes) are loaded.
     pub num_seed_bytes: Option<u32>,
     /// A vector of seed bytes to load.
     pub p_seed_bytes: Option<Vec<u8>>,
@@ -31,7 +33,7 @@ pub struct CompressionOptions {
 }
 
 /// The level of compression.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CompressionLevel {
     /// Fastest compression.
     Fastest,
@@ -48,7 +50,7 @@ pub enum CompressionLevel {
 /// Flag to tweak compression.
 ///
 /// Currently unimplemented.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum CompressionFlag {
     ExtremeParsing = 2,
     DeterminisiticParsing = 4,
@@ -56,6 +58,24 @@ pub enum CompressionFlag {
     WriteZlibStream = 32,
 }
 
+/// The flush mode of [`Compressor::compress_buf`].
+///
+/// [`Compressor::compress_buf`]: super::Compressor::compress_buf
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionFlush {
+    /// Does not flush. The compressor decides when to output data.
+    None = 0,
+    /// Outputs all the pending data, so the decompressor can decode all the input so far.
+    Sync = 2,
+    /// Like [`Sync`](Self::Sync), but also resets the compressor state, so the following
+    /// data does not depend on the previous data.
+    Full = 3,
+    /// Finishes the stream.
+    Finish = 4,
+    /// Like [`Sync`](Self::Sync), but also forces the Huffman tables to be updated.
+    Table = 10,
+}
+
 /// The status of compression.
 #[derive(Clone, Copy, Debug)]
 pub enum CompressionStatus {
@@ -84,6 +104,24 @@ impl Default for CompressionOptions {
     }
 }
 
+impl CompressionOptions {
+    /// Returns the table update interval used by the native codec.
+    ///
+    /// It is the specified interval, or the default one.
+    pub(crate) fn effective_table_update_interval(&self) -> TableUpdateInterval {
+        self.table_update_interval.unwrap_or_default()
+    }
+
+    /// Returns the number of seed bytes loaded by the native codec.
+    pub(crate) fn effective_num_seed_bytes(&self) -> usize {
+        // The number of seed bytes must never exceed the length of the seed buffer.
+        self.p_seed_bytes.as_deref().map_or(0, |p| {
+            self.num_seed_bytes
+                .map_or(p.len(), |n| p.len().min(n as usize))
+        })
+    }
+}
+
 impl BitOr for CompressionFlag {
     type Output = Self;
 
@@ -109,11 +147,7 @@ impl CompressionStatus {
 
     /// Whether the status is of success or not.
     pub fn is_success(&self) -> bool {
-        if let Self::Success = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Success)
     }
 
     /// Whether the status is success or first failure code.
@@ -129,11 +163,13 @@ impl CompressionStatus {
  * ========================================================
 */
 
-impl CType for CompressionOptions {
+impl CType for &CompressionOptions {
     type CItem = lzham_compress_params;
 
     fn to_c_type(self) -> Self::CItem {
-        let (max, slow) = self.table_update_interval.unwrap_or_default().to_c_type();
+        let (max, slow) = self.effective_table_update_interval().to_c_type();
+        let seed_bytes = self.p_seed_bytes.as_deref();
+        let num_seed_bytes = self.effective_num_seed_bytes();
 
         lzham_compress_params {
             m_struct_size: std::mem::size_of::<lzham_compress_params>() as c_uint,
@@ -142,14 +178,8 @@ impl CType for CompressionOptions {
             m_table_update_rate: self.table_update_rate.to_c_type(),
             m_max_helper_threads: self.max_helper_threads as c_int,
             m_compress_flags: self.compression_flags.map_or_else(|| 0, |f| f.to_c_type()),
-            m_num_seed_bytes: self.num_seed_bytes.unwrap_or(0) as c_uint,
-            m_pSeed_bytes: {
-                if let Some(p) = self.p_seed_bytes {
-                    p.as_ptr() as *const c_void
-                } else {
-                    std::ptr::null()
-                }
-            },
+            m_num_seed_bytes: num_seed_bytes as c_uint,
+            m_pSeed_bytes: seed_bytes.map_or_else(std::ptr::null, |p| p.as_ptr() as *const c_void),
             m_table_max_update_interval: max,
             m_table_update_interval_slow_rate: slow,
         }
@@ -172,6 +202,14 @@ impl CType for CompressionFlag {
     }
 }
 
+impl CType for CompressionFlush {
+    type CItem = lzham_sys::lzham_flush_t;
+
+    fn to_c_type(self) -> Self::CItem {
+        self as c_uint
+    }
+}
+
 impl CType for CompressionStatus {
     type CItem = lzham_sys::lzham_compress_status_t;
 
@@ -2,102 +2,512 @@ mod structures;
 
 pub use structures::*;
 
-use super::traits::CType;
+use super::{traits::CType, BUF_SIZE};
+use crate::{
+    memory,
+    progress::{CancellationToken, Progress, Tracker},
+    report::DecompressionReport,
+    DecompressionFailure, Error, Result,
+};
 use lzham_sys::lzham_decompress_state_ptr;
 use std::{
     io::{BufRead, Write},
     os::raw::c_uint,
 };
 
+/// The minimum number of bytes reserved when a vector runs out of capacity.
+const MIN_VEC_GROWTH: usize = 1 << 12;
+
+/// How a decompression loop ended.
+struct Outcome {
+    status: DecompressionStatus,
+    /// The status of the last call to the codec before the final one.
+    last_status: Option<DecompressionStatus>,
+    /// Whether the codec consumed all of the input.
+    input_exhausted: bool,
+}
+
 /// A high level decompressor.
-#[derive(Clone, Debug)]
-pub struct Decompressor(lzham_decompress_state_ptr);
+///
+/// The native state is released when the decompressor is dropped.
+#[derive(Debug)]
+pub struct Decompressor {
+    state: lzham_decompress_state_ptr,
+    // The native state keeps pointing to the seed bytes, so the options must outlive it.
+    options: DecompressionOptions,
+    total_in: u64,
+    total_out: u64,
+}
 
 impl Decompressor {
     /// Creates a new [`Decompressor`] from [`options`].
     ///
     /// [`options`]: DecompressionOptions
     pub fn from_options(options: DecompressionOptions) -> Self {
-        Self(unsafe { lzham_sys::lzham_decompress_init(&options.to_c_type()) })
+        memory::install();
+
+        let state = unsafe { lzham_sys::lzham_decompress_init(&(&options).to_c_type()) };
+
+        Self {
+            state,
+            options,
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    /// Returns the [`options`] the decompressor was created or last reinitialized with.
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn options(&self) -> &DecompressionOptions {
+        &self.options
+    }
+
+    /// Reinitializes the decompressor with new [`options`], so it can decompress a new stream.
+    ///
+    /// It reuses the already allocated native state when the dictionary does not grow and
+    /// the flags are the same, and creates a new one otherwise. Returns whether the
+    /// reinitialization succeeded.
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn reinit(&mut self, options: DecompressionOptions) -> bool {
+        // The native reinitialization keeps the buffers of the old state, which are sized
+        // for its dictionary and flags.
+        let reusable = !self.state.is_null()
+            && options.dict_size_log2 <= self.options.dict_size_log2
+            && options.decompression_flags == self.options.decompression_flags;
+        self.options = options;
+        if reusable {
+            return self.reset();
+        }
+
+        let state = unsafe { lzham_sys::lzham_decompress_init(&(&self.options).to_c_type()) };
+        let old = std::mem::replace(&mut self.state, state);
+        if !old.is_null() {
+            unsafe { lzham_sys::lzham_decompress_deinit(old) };
+        }
+        self.total_in = 0;
+        self.total_out = 0;
+
+        !self.state.is_null()
     }
 
-    /// Reinitializes the decompressor.
-    pub fn reinit(&self, options: DecompressionOptions) -> Self {
-        Self(unsafe { lzham_sys::lzham_decompress_reinit(self.0, &options.to_c_type()) })
+    /// Reinitializes the decompressor with its current options.
+    pub(crate) fn reset(&mut self) -> bool {
+        let state =
+            unsafe { lzham_sys::lzham_decompress_reinit(self.state, &(&self.options).to_c_type()) };
+
+        // On failure, the old state is still allocated and is released on drop.
+        if state.is_null() {
+            false
+        } else {
+            self.state = state;
+            self.total_in = 0;
+            self.total_out = 0;
+            true
+        }
     }
 
-    /// Deinitializes the decompressor.
+    /// Returns the number of compressed bytes consumed since the decompressor was created or
+    /// last reinitialized.
+    ///
+    /// The codec stops consuming input at the end of the stream, so once a stream is
+    /// decompressed, it is the exact size of the stream, and any trailing bytes are left in
+    /// the input.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// Returns the number of decompressed bytes produced since the decompressor was created
+    /// or last reinitialized.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// Deinitializes the decompressor and returns the adler32 of the decompressed data.
     ///
     /// It cannot be used to decompress further.
-    pub fn deinit(&self) -> u32 {
-        unsafe { lzham_sys::lzham_decompress_deinit(self.0) }
+    pub fn deinit(mut self) -> u32 {
+        let state = std::mem::replace(&mut self.state, std::ptr::null_mut());
+
+        unsafe { lzham_sys::lzham_decompress_deinit(state) }
+    }
+
+    /// Decompresses as much of `input` into `output` as possible.
+    ///
+    /// Returns the status, the number of bytes read from `input` and the number of bytes
+    /// written to `output`. `no_more_input` indicates that `input` holds the rest of the
+    /// stream.
+    ///
+    /// It can be called repeatedly to decompress a stream incrementally. Once it is called
+    /// with `no_more_input` set, it must stay set in all the following calls. It does not
+    /// read past the end of the stream.
+    pub fn decompress_buf(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        no_more_input: bool,
+    ) -> (DecompressionStatus, usize, usize) {
+        if self.state.is_null() {
+            return (DecompressionStatus::FailedInitialization, 0, 0);
+        }
+
+        let mut in_len = input.len() as lzham_sys::size_t;
+        let mut out_len = output.len() as lzham_sys::size_t;
+
+        let status_int = unsafe {
+            lzham_sys::lzham_decompress(
+                self.state,
+                input.as_ptr(),
+                &mut in_len,
+                output.as_mut_ptr(),
+                &mut out_len,
+                no_more_input as c_uint,
+            )
+        };
+
+        self.total_in += in_len as u64;
+        self.total_out += out_len as u64;
+
+        (
+            DecompressionStatus::from_c_type(status_int),
+            in_len as usize,
+            out_len as usize,
+        )
     }
 
     /// Decompresses input data into the output buffer with already specified [`options`].
     ///
+    /// The input is read and the output is written incrementally. Only the bytes of the
+    /// stream are consumed, so any data following it is left in `input`, and the size of the
+    /// stream is given by [`total_in`](Decompressor::total_in). If the decompressed data is
+    /// larger than `uncompressed_size`, it returns
+    /// [`OutputBufferTooSmall`](DecompressionStatus::OutputBufferTooSmall).
+    ///
     /// [`options`]: DecompressionOptions
     pub fn decompress<R: BufRead, W: Write>(
-        &self,
+        &mut self,
         input: &mut R,
         output: &mut W,
         uncompressed_size: usize,
     ) -> DecompressionStatus {
-        let mut input_buf = Vec::new();
-        if input.read_to_end(&mut input_buf).is_err() {
-            return DecompressionStatus::Failed;
+        self.decompress_stream(
+            input,
+            output,
+            uncompressed_size,
+            None,
+            &mut |_| {},
+            &mut Tracker::new(),
+        )
+        .map_or(DecompressionStatus::Failed, |outcome| outcome.status)
+    }
+
+    /// Decompresses input data into the output buffer and returns a [`DecompressionReport`].
+    ///
+    /// If the decompressed data is larger than `uncompressed_size`, it returns
+    /// [`Error::OutputBufferTooSmall`]. It fails with [`Error::DecompressionFailure`] if the
+    /// decompression does not succeed otherwise.
+    pub fn decompress_with_report<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        uncompressed_size: usize,
+    ) -> Result<DecompressionReport> {
+        let mut tracker = Tracker::new();
+        let outcome = self.decompress_stream(
+            input,
+            output,
+            uncompressed_size,
+            None,
+            &mut |_| {},
+            &mut tracker,
+        )?;
+
+        self.report(outcome, &tracker)
+    }
+
+    /// Decompresses input data into the output buffer, reporting the progress.
+    ///
+    /// `progress` is called after every iteration of the native codec, and `cancel` is
+    /// checked before every iteration. If the token is cancelled, it stops and returns
+    /// [`Error::Cancelled`]. The decompressor must then be [reinitialized] before it is used
+    /// again.
+    ///
+    /// If the decompressed data is larger than `uncompressed_size`, it returns
+    /// [`Error::OutputBufferTooSmall`].
+    ///
+    /// [reinitialized]: Decompressor::reinit
+    pub fn decompress_with_progress<R, W, F>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        uncompressed_size: usize,
+        cancel: &CancellationToken,
+        mut progress: F,
+    ) -> Result<DecompressionReport>
+    where
+        R: BufRead,
+        W: Write,
+        F: FnMut(&Progress),
+    {
+        let mut tracker = Tracker::new();
+        let outcome = self.decompress_stream(
+            input,
+            output,
+            uncompressed_size,
+            Some(cancel),
+            &mut progress,
+            &mut tracker,
+        )?;
+
+        self.report(outcome, &tracker)
+    }
+
+    fn decompress_stream<R: BufRead, W: Write>(
+        &mut self,
+        input: &mut R,
+        output: &mut W,
+        limit: usize,
+        cancel: Option<&CancellationToken>,
+        progress: &mut dyn FnMut(&Progress),
+        tracker: &mut Tracker,
+    ) -> Result<Outcome> {
+        let mut out_buf = [0; BUF_SIZE];
+        let mut remaining = limit;
+        let mut last_status = None;
+
+        loop {
+            if matches!(cancel, Some(cancel) if cancel.is_cancelled()) {
+                return Err(Error::Cancelled);
+            }
+
+            let in_buf = input.fill_buf()?;
+            let no_more_input = in_buf.is_empty();
+            let avail = remaining.min(BUF_SIZE);
+
+            let (status, read, written) =
+                self.decompress_buf(in_buf, &mut out_buf[..avail], no_more_input);
+
+            input.consume(read);
+            output.write_all(&out_buf[..written])?;
+            remaining -= written;
+            progress(tracker.update(read, written));
+
+            let status = match status {
+                status if status.is_success_or_first_failure() => status,
+                // The end of the stream may still be read into a full output.
+                DecompressionStatus::HasMoreOutput if avail == 0 => {
+                    DecompressionStatus::OutputBufferTooSmall
+                }
+                status => {
+                    last_status = Some(status);
+                    continue;
+                }
+            };
+
+            return Ok(Outcome {
+                status,
+                last_status,
+                input_exhausted: no_more_input,
+            });
         }
+    }
 
-        let mut output_buffer: Vec<u8> = Vec::with_capacity(uncompressed_size);
-        let uncompressed_size_szt = uncompressed_size as lzham_sys::size_t;
+    /// Decompresses `input` and appends the decompressed data to `output`.
+    ///
+    /// The data is decompressed directly into the spare capacity of `output`, which grows as
+    /// needed. If the decompressed data is larger than `limit`, it returns
+    /// [`OutputBufferTooSmall`](DecompressionStatus::OutputBufferTooSmall).
+    pub fn decompress_to_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> DecompressionStatus {
+        self.decompress_vec(input, output, limit, &mut Tracker::new())
+            .status
+    }
+
+    /// Decompresses `input`, appends the decompressed data to `output` and returns a
+    /// [`DecompressionReport`].
+    ///
+    /// If the decompressed data is larger than `limit`, it returns
+    /// [`Error::OutputBufferTooSmall`]. It fails with [`Error::DecompressionFailure`] if the
+    /// decompression does not succeed otherwise.
+    pub fn decompress_to_vec_with_report(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<DecompressionReport> {
+        let mut tracker = Tracker::new();
+        let outcome = self.decompress_vec(input, output, limit, &mut tracker);
 
-        let mut in_buf_ofs = 0;
-        let mut out_buf_ofs = 0;
-        let mut dst_bytes_left = uncompressed_size_szt;
+        self.report(outcome, &tracker)
+    }
 
-        let mut status;
+    /// Decompresses `input` into the `output` slice and returns a [`DecompressionReport`].
+    ///
+    /// Unlike [`decompress_into`](crate::decompress_into), it goes through the streaming
+    /// interface of the native codec, which reports the size of the stream. If the
+    /// decompressed data is larger than `output`, it returns [`Error::OutputBufferTooSmall`].
+    /// It fails with [`Error::DecompressionFailure`] if the decompression does not succeed
+    /// otherwise.
+    pub fn decompress_into_with_report(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<DecompressionReport> {
+        let mut tracker = Tracker::new();
+        let outcome = self.decompress_slice(input, output, &mut tracker);
 
-        let mut num_in_bytes;
-        let mut out_buf_len;
+        self.report(outcome, &tracker)
+    }
+
+    fn decompress_slice(
+        &mut self,
+        mut input: &[u8],
+        output: &mut [u8],
+        tracker: &mut Tracker,
+    ) -> Outcome {
+        let mut len = 0;
+        let mut last_status = None;
 
         loop {
-            num_in_bytes = input_buf.len() as lzham_sys::size_t;
-            out_buf_len = uncompressed_size_szt - out_buf_ofs;
-
-            let status_int = unsafe {
-                lzham_sys::lzham_decompress(
-                    self.0,
-                    input_buf.as_ptr().add(in_buf_ofs as usize),
-                    &mut num_in_bytes,
-                    output_buffer.as_mut_ptr().add(out_buf_ofs as usize),
-                    &mut out_buf_len,
-                    1 as c_uint,
-                )
+            let avail = output.len() - len;
+            let (status, read, written) = self.decompress_buf(input, &mut output[len..], true);
+
+            input = &input[read..];
+            len += written;
+            tracker.update(read, written);
+
+            let status = match status {
+                status if status.is_success_or_first_failure() => status,
+                // The end of the stream may still be read into a full output.
+                DecompressionStatus::HasMoreOutput if avail == 0 => {
+                    DecompressionStatus::OutputBufferTooSmall
+                }
+                status => {
+                    last_status = Some(status);
+                    continue;
+                }
             };
 
-            in_buf_ofs += num_in_bytes;
-            out_buf_ofs += out_buf_len;
+            return Outcome {
+                status,
+                last_status,
+                input_exhausted: input.is_empty(),
+            };
+        }
+    }
+
+    fn decompress_vec(
+        &mut self,
+        mut input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+        tracker: &mut Tracker,
+    ) -> Outcome {
+        let mut remaining = limit;
+        let mut last_status = None;
 
-            if out_buf_len > dst_bytes_left {
-                return DecompressionStatus::Failed;
+        loop {
+            if output.len() == output.capacity() && remaining > 0 {
+                output.reserve(output.capacity().max(MIN_VEC_GROWTH).min(remaining));
+                tracker.grow();
             }
 
-            dst_bytes_left -= out_buf_len;
+            let len = output.len();
+            let avail = (output.capacity() - len).min(remaining);
+            output.resize(len + avail, 0);
 
-            status = DecompressionStatus::from_c_type(status_int);
+            let (status, read, written) = self.decompress_buf(input, &mut output[len..], true);
 
-            if status.is_success_or_first_failure() {
-                break;
-            }
-        }
+            output.truncate(len + written);
+            input = &input[read..];
+            remaining -= written;
+            tracker.update(read, written);
 
-        unsafe {
-            output_buffer.set_len(uncompressed_size);
+            let status = match status {
+                status if status.is_success_or_first_failure() => status,
+                // The end of the stream may still be read into a full output.
+                DecompressionStatus::HasMoreOutput if avail == 0 => {
+                    DecompressionStatus::OutputBufferTooSmall
+                }
+                status => {
+                    last_status = Some(status);
+                    continue;
+                }
+            };
+
+            return Outcome {
+                status,
+                last_status,
+                input_exhausted: input.is_empty(),
+            };
         }
+    }
+
+    fn report(&self, outcome: Outcome, tracker: &Tracker) -> Result<DecompressionReport> {
+        match outcome.status {
+            DecompressionStatus::Success => Ok(DecompressionReport::new(tracker, &self.options)),
+            DecompressionStatus::OutputBufferTooSmall => Err(Error::OutputBufferTooSmall(None)),
+            status => {
+                let progress = tracker.progress();
 
-        if output.write(&output_buffer).is_err() {
-            return DecompressionStatus::Failed;
+                Err(Error::DecompressionFailure(Box::new(
+                    DecompressionFailure {
+                        status,
+                        compressed_offset: progress.input_consumed,
+                        uncompressed_offset: progress.output_produced,
+                        last_status: outcome.last_status,
+                        input_exhausted: outcome.input_exhausted,
+                        member: None,
+                        options: self.options.clone(),
+                    },
+                )))
+            }
         }
+    }
+}
+
+// The native state is not tied to the thread that created it.
+unsafe impl Send for Decompressor {}
 
-        status
+impl Drop for Decompressor {
+    fn drop(&mut self) {
+        if !self.state.is_null() {
+            unsafe { lzham_sys::lzham_decompress_deinit(self.state) };
+        }
     }
 }
+
+/// Decompresses `input` into `output` in a single call to the native codec.
+///
+/// Returns the status and the size of the decompressed data.
+pub(crate) fn decompress_memory(
+    input: &[u8],
+    output: &mut [u8],
+    options: &DecompressionOptions,
+) -> (DecompressionStatus, usize) {
+    memory::install();
+
+    let mut out_len = output.len() as lzham_sys::size_t;
+    let mut adler32 = 0;
+
+    let status_int = unsafe {
+        lzham_sys::lzham_decompress_memory(
+            &options.to_c_type(),
+            output.as_mut_ptr(),
+            &mut out_len,
+            input.as_ptr(),
+            input.len() as lzham_sys::size_t,
+            &mut adler32,
+        )
+    };
+
+    (
+        DecompressionStatus::from_c_type(status_int),
+        out_len as usize,
+    )
+}
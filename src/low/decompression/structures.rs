@@ -6,7 +6,7 @@ use std::{
 };
 
 /// Options to tweak decompression.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct DecompressionOptions {
     /// The base 2 log of the dictionary size.
     pub dict_size_log2: u32,
@@ -19,6 +19,8 @@ pub struct DecompressionOptions {
     /// Currently unimplemented.
     pub decompression_flags: Option<DecompressionFlag>,
     /// Number of seed bytes to load.
+    ///
+    /// If not specified, all of [`p_seed_bytes`](Self::p_seed_bytes) are loaded.
     pub num_seed_bytes: Option<u32>,
     /// A vector of seed bytes to load.
     pub p_seed_bytes: Option<Vec<u8>>,
@@ -29,7 +31,7 @@ pub struct DecompressionOptions {
 /// Flag to tweak decompression.
 ///
 /// Currently unimplemented.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum DecompressionFlag {
     OutputUnbuffered = 1,
     ComputeAdler32 = 2,
@@ -72,6 +74,24 @@ impl Default for DecompressionOptions {
     }
 }
 
+impl DecompressionOptions {
+    /// Returns the table update interval used by the native codec.
+    ///
+    /// It is the specified interval, or the default one.
+    pub(crate) fn effective_table_update_interval(&self) -> TableUpdateInterval {
+        self.table_update_interval.unwrap_or_default()
+    }
+
+    /// Returns the number of seed bytes loaded by the native codec.
+    pub(crate) fn effective_num_seed_bytes(&self) -> usize {
+        // The number of seed bytes must never exceed the length of the seed buffer.
+        self.p_seed_bytes.as_deref().map_or(0, |p| {
+            self.num_seed_bytes
+                .map_or(p.len(), |n| p.len().min(n as usize))
+        })
+    }
+}
+
 impl BitOr for DecompressionFlag {
     type Output = Self;
 
@@ -105,11 +125,7 @@ impl DecompressionStatus {
 
     /// Whether the status is of success or not.
     pub fn is_success(&self) -> bool {
-        if let Self::Success = self {
-            true
-        } else {
-            false
-        }
+        matches!(self, Self::Success)
     }
 
     /// Whether the status is success or first failure code.
@@ -125,11 +141,13 @@ impl DecompressionStatus {
  * ========================================================
 */
 
-impl CType for DecompressionOptions {
+impl CType for &DecompressionOptions {
     type CItem = lzham_decompress_params;
 
     fn to_c_type(self) -> Self::CItem {
-        let (max, slow) = self.table_update_interval.unwrap_or_default().to_c_type();
+        let (max, slow) = self.effective_table_update_interval().to_c_type();
+        let seed_bytes = self.p_seed_bytes.as_deref();
+        let num_seed_bytes = self.effective_num_seed_bytes();
 
         lzham_decompress_params {
             m_struct_size: std::mem::size_of::<lzham_decompress_params>() as c_uint,
@@ -138,14 +156,8 @@ impl CType for DecompressionOptions {
             m_decompress_flags: self
                 .decompression_flags
                 .map_or_else(|| 0, |f| f.to_c_type()),
-            m_num_seed_bytes: self.num_seed_bytes.unwrap_or(0) as c_uint,
-            m_pSeed_bytes: {
-                if let Some(p) = self.p_seed_bytes {
-                    p.as_ptr() as *const c_void
-                } else {
-                    std::ptr::null()
-                }
-            },
+            m_num_seed_bytes: num_seed_bytes as c_uint,
+            m_pSeed_bytes: seed_bytes.map_or_else(std::ptr::null, |p| p.as_ptr() as *const c_void),
             m_table_max_update_interval: max,
             m_table_update_interval_slow_rate: slow,
         }
@@ -0,0 +1,236 @@
+//! Memory management of the native codec.
+//!
+//! The [`lzham_codec`] allocates its state through a set of memory callbacks. This crate
+//! installs its own callbacks before the first compressor or decompressor is created, so
+//! every native allocation goes through a Rust allocator. By default, that is the global
+//! allocator of the program (see [`#[global_allocator]`]), but it can be changed with
+//! [`set_allocator`], for example to place the codec memory in an arena.
+//!
+//! The callbacks also keep per-process counters of the codec memory, which can be read
+//! with [`stats`]. The peak can be reset with [`reset_peak`].
+//!
+//! Note that allocations made by calling [`lzham_sys`] directly before any compressor or
+//! decompressor of this crate is created do not go through these callbacks, and must not
+//! be freed after they are installed.
+//!
+//! [`lzham_codec`]: https://github.com/richgel999/lzham_codec
+//! [`#[global_allocator]`]: std::alloc::GlobalAlloc
+
+use std::{
+    alloc::{GlobalAlloc, Layout},
+    mem::size_of,
+    os::raw::c_void,
+    ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Once, RwLock,
+    },
+};
+
+/// Statistics about the memory allocated by the native codec.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MemoryStats {
+    /// The number of bytes currently allocated.
+    pub live_bytes: usize,
+    /// The highest number of bytes allocated at the same time.
+    pub peak_bytes: usize,
+    /// The total number of allocations made.
+    pub allocations: usize,
+}
+
+/// Returns the statistics about the memory allocated by the native codec.
+///
+/// The counters are shared by all compressors and decompressors in the process.
+pub fn stats() -> MemoryStats {
+    MemoryStats {
+        live_bytes: LIVE_BYTES.load(Ordering::Relaxed),
+        peak_bytes: PEAK_BYTES.load(Ordering::Relaxed),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets the peak counter to the number of bytes currently allocated.
+///
+/// This allows measuring the peak memory of a single operation, as long as no other
+/// compressor or decompressor runs at the same time.
+pub fn reset_peak() {
+    PEAK_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+}
+
+/// Sets the allocator used for the new allocations of the native codec.
+///
+/// Memory which is already allocated is still freed by the allocator that allocated it,
+/// so it is safe to call this function at any time.
+pub fn set_allocator(allocator: &'static (dyn GlobalAlloc + Sync)) {
+    *ALLOCATOR.write().unwrap_or_else(|e| e.into_inner()) = allocator;
+}
+
+/// Allocator which forwards to the global allocator of the program.
+struct RustAllocator;
+
+unsafe impl GlobalAlloc for RustAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        std::alloc::alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        std::alloc::dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        std::alloc::realloc(ptr, layout, new_size)
+    }
+}
+
+static ALLOCATOR: RwLock<&'static (dyn GlobalAlloc + Sync)> = RwLock::new(&RustAllocator);
+
+static LIVE_BYTES: AtomicUsize = AtomicUsize::new(0);
+static PEAK_BYTES: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+static INSTALL: Once = Once::new();
+
+/// Installs the memory callbacks into the native codec.
+///
+/// It must be called before any native state is created.
+pub(crate) fn install() {
+    INSTALL.call_once(|| unsafe {
+        lzham_sys::lzham_set_memory_callbacks(Some(realloc), Some(msize), ptr::null_mut());
+    });
+}
+
+/*
+ * Native callbacks
+ * ========================================================
+*/
+
+/// The alignment required by the native codec (`LZHAM_MIN_ALLOC_ALIGNMENT`).
+const ALIGN: usize = size_of::<usize>() * 2;
+
+/// Bookkeeping stored in front of every block handed to the native codec.
+struct Header {
+    size: usize,
+    allocator: &'static (dyn GlobalAlloc + Sync),
+}
+
+/// The size of [`Header`], rounded up to keep the blocks aligned.
+const HEADER_SIZE: usize = (size_of::<Header>() + ALIGN - 1) & !(ALIGN - 1);
+
+fn layout(size: usize) -> Option<Layout> {
+    Layout::from_size_align(size.checked_add(HEADER_SIZE)?, ALIGN).ok()
+}
+
+unsafe fn header<'a>(block: *mut c_void) -> &'a mut Header {
+    &mut *(block as *mut u8).sub(HEADER_SIZE).cast::<Header>()
+}
+
+fn track_alloc(size: usize) {
+    ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    let live = LIVE_BYTES.fetch_add(size, Ordering::Relaxed) + size;
+    PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+}
+
+fn track_dealloc(size: usize) {
+    LIVE_BYTES.fetch_sub(size, Ordering::Relaxed);
+}
+
+unsafe fn allocate(size: usize) -> *mut c_void {
+    let layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+
+    let allocator = *ALLOCATOR.read().unwrap_or_else(|e| e.into_inner());
+    let base = allocator.alloc(layout);
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+
+    base.cast::<Header>().write(Header { size, allocator });
+    track_alloc(size);
+
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe fn reallocate(block: *mut c_void, size: usize) -> *mut c_void {
+    let Header {
+        size: old_size,
+        allocator,
+    } = *header(block);
+
+    let new_layout = match layout(size) {
+        Some(layout) => layout,
+        None => return ptr::null_mut(),
+    };
+    let old_layout = Layout::from_size_align_unchecked(old_size + HEADER_SIZE, ALIGN);
+
+    let base = allocator.realloc(
+        (block as *mut u8).sub(HEADER_SIZE),
+        old_layout,
+        new_layout.size(),
+    );
+    if base.is_null() {
+        return ptr::null_mut();
+    }
+
+    (*base.cast::<Header>()).size = size;
+    track_dealloc(old_size);
+    track_alloc(size);
+
+    base.add(HEADER_SIZE) as *mut c_void
+}
+
+unsafe fn release(block: *mut c_void) {
+    let Header { size, allocator } = *header(block);
+
+    allocator.dealloc(
+        (block as *mut u8).sub(HEADER_SIZE),
+        Layout::from_size_align_unchecked(size + HEADER_SIZE, ALIGN),
+    );
+    track_dealloc(size);
+}
+
+unsafe extern "C" fn realloc(
+    block: *mut c_void,
+    size: lzham_sys::size_t,
+    actual_size: *mut lzham_sys::size_t,
+    movable: lzham_sys::lzham_bool,
+    _user_data: *mut c_void,
+) -> *mut c_void {
+    let size = size as usize;
+
+    let (new_block, new_size) = if block.is_null() {
+        let new_block = allocate(size);
+
+        (new_block, if new_block.is_null() { 0 } else { size })
+    } else if size == 0 {
+        release(block);
+
+        (ptr::null_mut(), 0)
+    } else if movable != 0 {
+        let new_block = reallocate(block, size);
+
+        if new_block.is_null() {
+            (new_block, header(block).size)
+        } else {
+            (new_block, size)
+        }
+    } else {
+        // Blocks cannot be grown or shrunk in place.
+        (ptr::null_mut(), header(block).size)
+    };
+
+    if !actual_size.is_null() {
+        *actual_size = new_size as lzham_sys::size_t;
+    }
+
+    new_block
+}
+
+unsafe extern "C" fn msize(block: *mut c_void, _user_data: *mut c_void) -> lzham_sys::size_t {
+    if block.is_null() {
+        0
+    } else {
+        header(block).size as lzham_sys::size_t
+    }
+}
@@ -0,0 +1,269 @@
+//! Message-oriented compression with a persistent context.
+//!
+//! Compressing every message of a protocol independently throws away the history window
+//! of the codec. [`MessageCompressor`] instead keeps a single stream open across messages,
+//! and flushes it at the end of every message, so [`MessageDecompressor`] can decode each
+//! message as soon as it arrives, while later messages still match against earlier ones.
+//!
+//! Both sides can reset their context every [`reset_interval`] messages, which bounds the
+//! damage of a lost or corrupted message and lets the receiver recover at the next reset.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::message::{MessageCompressor, MessageDecompressor, MessageOptions};
+//! let mut compressor = MessageCompressor::new(Default::default(), MessageOptions::default());
+//! let mut decompressor = MessageDecompressor::new(Default::default(), MessageOptions::default());
+//!
+//! for message in [&b"This is a test."[..], b"This is another test."] {
+//!     let mut comp = Vec::new();
+//!     compressor.compress_message(message, &mut comp).unwrap();
+//!
+//!     let mut decomp = Vec::new();
+//!     decompressor.decompress_message(&comp, &mut decomp, 1024).unwrap();
+//!
+//!     assert_eq!(decomp, message);
+//! }
+//! ```
+//!
+//! [`reset_interval`]: MessageOptions::reset_interval
+
+use crate::{
+    compress::{CompressionFlush, CompressionStatus, Compressor},
+    decompress::{DecompressionStatus, Decompressor},
+    CompressionOptions, DecompressionOptions, Error, Result,
+};
+
+/// The minimum number of bytes reserved when a vector runs out of capacity.
+const MIN_VEC_GROWTH: usize = 1 << 12;
+
+/// Options of the message mode.
+///
+/// Both sides of a connection must use the same [`reset_interval`].
+///
+/// [`reset_interval`]: MessageOptions::reset_interval
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageOptions {
+    /// The flush emitted at the end of every message.
+    ///
+    /// [`CompressionFlush::Sync`] keeps the whole context. [`CompressionFlush::Full`] also
+    /// resets the match finder, which costs some ratio. [`CompressionFlush::None`] and
+    /// [`CompressionFlush::Finish`] are treated as [`CompressionFlush::Sync`]. It is ignored
+    /// by the decompressor.
+    pub flush: CompressionFlush,
+    /// Resets the context after every `reset_interval` messages.
+    ///
+    /// If not specified or zero, the context is never reset.
+    pub reset_interval: Option<usize>,
+}
+
+impl Default for MessageOptions {
+    fn default() -> Self {
+        Self {
+            flush: CompressionFlush::Sync,
+            reset_interval: None,
+        }
+    }
+}
+
+impl MessageOptions {
+    /// Returns whether the context must be reset before the message after `messages`
+    /// messages.
+    fn must_reset(&self, messages: usize) -> bool {
+        match self.reset_interval {
+            Some(interval) if interval > 0 => messages > 0 && messages % interval == 0,
+            _ => false,
+        }
+    }
+}
+
+/// Compresses messages into a single stream, flushed at the end of every message.
+#[derive(Debug)]
+pub struct MessageCompressor {
+    compressor: Compressor,
+    options: MessageOptions,
+    messages: usize,
+}
+
+/// Decompresses messages compressed by a [`MessageCompressor`].
+#[derive(Debug)]
+pub struct MessageDecompressor {
+    decompressor: Decompressor,
+    options: MessageOptions,
+    messages: usize,
+}
+
+impl MessageCompressor {
+    /// Creates a new message compressor with provided [`options`] and message options.
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn new(options: CompressionOptions, message_options: MessageOptions) -> Self {
+        Self {
+            compressor: Compressor::from_options(options),
+            options: message_options,
+            messages: 0,
+        }
+    }
+
+    /// Returns the number of messages compressed since the context was last reset.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// Compresses `message` and appends the compressed data to `output`.
+    ///
+    /// The output holds everything needed to decode the message, given the previous
+    /// messages since the last reset. If it fails, the context is broken until the next
+    /// reset.
+    pub fn compress_message(&mut self, message: &[u8], output: &mut Vec<u8>) -> Result<()> {
+        if self.options.must_reset(self.messages) {
+            self.reset()?;
+        }
+        self.messages += 1;
+
+        let flush = match self.options.flush {
+            CompressionFlush::None | CompressionFlush::Finish => CompressionFlush::Sync,
+            flush => flush,
+        };
+
+        // The flush is only applied once the codec has no pending output, so the message is
+        // passed first, and then the flush on its own.
+        match self.compress(message, output, CompressionFlush::None) {
+            CompressionStatus::NeedsMoreInput => {}
+            status => return Err(Error::Compression(status)),
+        }
+
+        match self.compress(&[], output, flush) {
+            status if status.is_success_or_first_failure() => Err(Error::Compression(status)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resets the context, so the next message does not depend on the previous ones.
+    ///
+    /// The decompressor must be reset at the same message.
+    pub fn reset(&mut self) -> Result<()> {
+        self.messages = 0;
+
+        if self.compressor.reinit() {
+            Ok(())
+        } else {
+            Err(Error::Compression(CompressionStatus::FailedInitialization))
+        }
+    }
+
+    fn compress(
+        &mut self,
+        mut input: &[u8],
+        output: &mut Vec<u8>,
+        mut flush: CompressionFlush,
+    ) -> CompressionStatus {
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(MIN_VEC_GROWTH));
+            }
+
+            let len = output.len();
+            output.resize(output.capacity(), 0);
+
+            let (status, read, written) =
+                self.compressor
+                    .compress_buf(input, &mut output[len..], flush);
+
+            output.truncate(len + written);
+            input = &input[read..];
+
+            if status.is_success_or_first_failure() {
+                return status;
+            } else if input.is_empty() {
+                // The flush has been applied, the rest of the output is drained without it.
+                flush = CompressionFlush::None;
+
+                if !matches!(status, CompressionStatus::HasMoreOutput) {
+                    return status;
+                }
+            }
+        }
+    }
+}
+
+impl MessageDecompressor {
+    /// Creates a new message decompressor with provided [`options`] and message options.
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn new(options: DecompressionOptions, message_options: MessageOptions) -> Self {
+        Self {
+            decompressor: Decompressor::from_options(options),
+            options: message_options,
+            messages: 0,
+        }
+    }
+
+    /// Returns the number of messages decompressed since the context was last reset.
+    pub fn messages(&self) -> usize {
+        self.messages
+    }
+
+    /// Decompresses the complete compressed `message` and appends it to `output`.
+    ///
+    /// If the decompressed message is larger than `limit`, it returns
+    /// [`Error::OutputBufferTooSmall`]. If it fails, the context is broken until the next
+    /// reset, but the messages are still counted, so it recovers at the next reset.
+    pub fn decompress_message(
+        &mut self,
+        mut message: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<()> {
+        if self.options.must_reset(self.messages) {
+            self.reset()?;
+        }
+        self.messages += 1;
+
+        let mut remaining = limit;
+
+        loop {
+            if output.len() == output.capacity() {
+                output.reserve(output.capacity().max(MIN_VEC_GROWTH).min(remaining));
+            }
+
+            let len = output.len();
+            let avail = (output.capacity() - len).min(remaining);
+            output.resize(len + avail, 0);
+
+            let (status, read, written) =
+                self.decompressor
+                    .decompress_buf(message, &mut output[len..], false);
+
+            output.truncate(len + written);
+            message = &message[read..];
+            remaining -= written;
+
+            if status.is_success_or_first_failure() {
+                return Err(Error::Decompression(status));
+            } else if message.is_empty() && matches!(status, DecompressionStatus::NeedsMoreInput) {
+                // The codec has decoded everything up to the flush at the end of the message.
+                return Ok(());
+            } else if avail == 0 {
+                return Err(Error::OutputBufferTooSmall(None));
+            } else if read == 0 && written == 0 {
+                return Err(Error::Decompression(status));
+            }
+        }
+    }
+
+    /// Resets the context, so the next message does not depend on the previous ones.
+    ///
+    /// The compressor must be reset at the same message.
+    pub fn reset(&mut self) -> Result<()> {
+        self.messages = 0;
+
+        if self.decompressor.reset() {
+            Ok(())
+        } else {
+            Err(Error::Decompression(
+                DecompressionStatus::FailedInitialization,
+            ))
+        }
+    }
+}
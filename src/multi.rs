@@ -0,0 +1,117 @@
+//! Decompression of concatenated streams.
+//!
+//! Appending independently compressed streams to a single file, the way `cat a.gz b.gz`
+//! does with gzip, produces a multi-member input. [`decompress_multi`] decodes its members
+//! one after the other, resetting the decompressor between them, and writes the
+//! concatenation of their data.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::{compress_to_vec, multi::decompress_multi};
+//! let mut comp = compress_to_vec(b"This is a test.", &Default::default()).unwrap();
+//! comp.extend(compress_to_vec(b" This is another test.", &Default::default()).unwrap());
+//!
+//! let mut decomp = Vec::new();
+//! let report = decompress_multi(&mut comp.as_slice(), &mut decomp, 1024, &Default::default())
+//!     .unwrap();
+//!
+//! assert_eq!(report.members.len(), 2);
+//! assert_eq!(decomp, b"This is a test. This is another test.");
+//! ```
+
+use crate::{
+    decompress::{DecompressionStatus, Decompressor},
+    DecompressionOptions, Error, Result,
+};
+use std::{
+    io::{BufRead, Write},
+    ops::Range,
+};
+
+/// The location of a stream within a multi-member input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Member {
+    /// The compressed bytes of the stream.
+    pub compressed: Range<u64>,
+    /// The decompressed bytes of the stream in the output.
+    pub uncompressed: Range<u64>,
+}
+
+/// The result of [`decompress_multi`].
+#[derive(Clone, Debug, Default)]
+pub struct MultiReport {
+    /// The decoded streams, in order.
+    pub members: Vec<Member>,
+    /// The number of compressed bytes read.
+    pub bytes_in: u64,
+    /// The number of decompressed bytes written.
+    pub bytes_out: u64,
+}
+
+/// Decompresses concatenated streams from the input data into the output buffer with
+/// provided [`options`], until the end of the input.
+///
+/// Every stream must be complete. An empty input holds no stream and succeeds. If a stream
+/// fails, it returns [`Error::DecompressionFailure`] with the index of the stream in its
+/// [`member`] field, and offsets relative to the start of the whole input and output. Other
+/// errors are wrapped in [`Error::Member`] with the index of the stream, such as
+/// [`Error::OutputBufferTooSmall`] when the decompressed data is larger than
+/// `uncompressed_size`.
+///
+/// [`options`]: DecompressionOptions
+/// [`member`]: crate::DecompressionFailure::member
+pub fn decompress_multi<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    uncompressed_size: usize,
+    options: &DecompressionOptions,
+) -> Result<MultiReport> {
+    let mut decompressor = Decompressor::from_options(options.clone());
+    let mut report = MultiReport::default();
+    let mut remaining = uncompressed_size;
+
+    loop {
+        let index = report.members.len();
+        let in_member = |error| match error {
+            Error::DecompressionFailure(mut failure) => {
+                failure.member = Some(index);
+                failure.compressed_offset += report.bytes_in;
+                failure.uncompressed_offset += report.bytes_out;
+
+                Error::DecompressionFailure(failure)
+            }
+            error => Error::Member {
+                member: index,
+                error: Box::new(error),
+            },
+        };
+
+        if input
+            .fill_buf()
+            .map_err(|e| in_member(e.into()))?
+            .is_empty()
+        {
+            break;
+        }
+        if index > 0 && !decompressor.reset() {
+            return Err(in_member(Error::Decompression(
+                DecompressionStatus::FailedInitialization,
+            )));
+        }
+
+        let member = decompressor
+            .decompress_with_report(input, output, remaining)
+            .map_err(in_member)?;
+
+        report.members.push(Member {
+            compressed: report.bytes_in..report.bytes_in + member.bytes_in,
+            uncompressed: report.bytes_out..report.bytes_out + member.bytes_out,
+        });
+        report.bytes_in += member.bytes_in;
+        report.bytes_out += member.bytes_out;
+        remaining -= member.bytes_out as usize;
+    }
+
+    Ok(report)
+}
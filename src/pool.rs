@@ -0,0 +1,302 @@
+//! Pools of reusable compressors and decompressors.
+//!
+//! Creating a [`Compressor`] or a [`Decompressor`] allocates a native state of several
+//! megabytes. The pools keep idle states around and reinitialize them, so services which
+//! compress many small payloads only pay for the allocation once per concurrent user.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::{pool::CompressorPool, CompressionOptions};
+//! let pool = CompressorPool::new(8);
+//! let options = CompressionOptions::default();
+//!
+//! let mut comp = Vec::new();
+//! let status = pool.get(&options).compress_to_vec(b"This is a test.", &mut comp);
+//!
+//! assert!(status.is_success());
+//! ```
+
+use crate::{
+    compress::{CompressionOptions, Compressor},
+    decompress::{DecompressionOptions, Decompressor},
+};
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::Hash,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+/// Statistics about the use of a pool.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    /// The number of times an idle state was reused.
+    pub hits: usize,
+    /// The number of times a new state had to be created.
+    pub misses: usize,
+    /// The number of idle states currently kept by the pool.
+    pub idle: usize,
+}
+
+/// A thread-safe pool of [`Compressor`]s, keyed by their [`options`].
+///
+/// [`options`]: CompressionOptions
+#[derive(Debug)]
+pub struct CompressorPool(Pool<Compressor>);
+
+/// A thread-safe pool of [`Decompressor`]s, keyed by their [`options`].
+///
+/// [`options`]: DecompressionOptions
+#[derive(Debug)]
+pub struct DecompressorPool(Pool<Decompressor>);
+
+/// A [`Compressor`] borrowed from a [`CompressorPool`].
+///
+/// It is reinitialized and returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledCompressor<'a> {
+    pool: &'a Pool<Compressor>,
+    compressor: Option<Compressor>,
+}
+
+/// A [`Decompressor`] borrowed from a [`DecompressorPool`].
+///
+/// It is reinitialized and returned to the pool when dropped.
+#[derive(Debug)]
+pub struct PooledDecompressor<'a> {
+    pool: &'a Pool<Decompressor>,
+    decompressor: Option<Decompressor>,
+}
+
+impl CompressorPool {
+    /// Creates a new pool which keeps at most `max_idle` idle compressors.
+    pub fn new(max_idle: usize) -> Self {
+        Self(Pool::new(max_idle))
+    }
+
+    /// Returns a compressor created with `options`, ready to compress a new stream.
+    ///
+    /// It reuses an idle compressor if there is one, otherwise it creates a new one.
+    pub fn get(&self, options: &CompressionOptions) -> PooledCompressor<'_> {
+        PooledCompressor {
+            pool: &self.0,
+            compressor: Some(self.0.get(options)),
+        }
+    }
+
+    /// Returns the statistics of the pool.
+    pub fn stats(&self) -> PoolStats {
+        self.0.stats()
+    }
+}
+
+impl DecompressorPool {
+    /// Creates a new pool which keeps at most `max_idle` idle decompressors.
+    pub fn new(max_idle: usize) -> Self {
+        Self(Pool::new(max_idle))
+    }
+
+    /// Returns a decompressor created with `options`, ready to decompress a new stream.
+    ///
+    /// It reuses an idle decompressor if there is one, otherwise it creates a new one.
+    pub fn get(&self, options: &DecompressionOptions) -> PooledDecompressor<'_> {
+        PooledDecompressor {
+            pool: &self.0,
+            decompressor: Some(self.0.get(options)),
+        }
+    }
+
+    /// Returns the statistics of the pool.
+    pub fn stats(&self) -> PoolStats {
+        self.0.stats()
+    }
+}
+
+impl Deref for PooledCompressor<'_> {
+    type Target = Compressor;
+
+    fn deref(&self) -> &Compressor {
+        self.compressor.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledCompressor<'_> {
+    fn deref_mut(&mut self) -> &mut Compressor {
+        self.compressor.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledCompressor<'_> {
+    fn drop(&mut self) {
+        if let Some(compressor) = self.compressor.take() {
+            self.pool.put(compressor);
+        }
+    }
+}
+
+impl Deref for PooledDecompressor<'_> {
+    type Target = Decompressor;
+
+    fn deref(&self) -> &Decompressor {
+        self.decompressor.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledDecompressor<'_> {
+    fn deref_mut(&mut self) -> &mut Decompressor {
+        self.decompressor.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledDecompressor<'_> {
+    fn drop(&mut self) {
+        if let Some(decompressor) = self.decompressor.take() {
+            self.pool.put(decompressor);
+        }
+    }
+}
+
+/*
+ * Generic pool
+ * ========================================================
+*/
+
+/// A codec state which can be reused for new streams.
+trait Reusable: Sized {
+    type Options: Clone + Eq + Hash;
+
+    fn create(options: &Self::Options) -> Self;
+
+    fn options(&self) -> &Self::Options;
+
+    /// Prepares the state for a new stream. Returns whether it succeeded.
+    fn reset(&mut self) -> bool;
+}
+
+impl Reusable for Compressor {
+    type Options = CompressionOptions;
+
+    fn create(options: &CompressionOptions) -> Self {
+        Compressor::from_options(options.clone())
+    }
+
+    fn options(&self) -> &CompressionOptions {
+        Compressor::options(self)
+    }
+
+    fn reset(&mut self) -> bool {
+        self.reinit()
+    }
+}
+
+impl Reusable for Decompressor {
+    type Options = DecompressionOptions;
+
+    fn create(options: &DecompressionOptions) -> Self {
+        Decompressor::from_options(options.clone())
+    }
+
+    fn options(&self) -> &DecompressionOptions {
+        Decompressor::options(self)
+    }
+
+    fn reset(&mut self) -> bool {
+        Decompressor::reset(self)
+    }
+}
+
+struct Pool<T: Reusable> {
+    idle: Mutex<Idle<T>>,
+    max_idle: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+struct Idle<T: Reusable> {
+    states: HashMap<T::Options, Vec<T>>,
+    len: usize,
+}
+
+impl<T: Reusable> Pool<T> {
+    fn new(max_idle: usize) -> Self {
+        Self {
+            idle: Mutex::new(Idle {
+                states: HashMap::new(),
+                len: 0,
+            }),
+            max_idle,
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    fn get(&self, options: &T::Options) -> T {
+        let reused = {
+            let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+            let state = idle.states.get_mut(options).and_then(Vec::pop);
+            if state.is_some() {
+                idle.len -= 1;
+
+                // Options which are not used anymore must not stay in the map.
+                if matches!(idle.states.get(options), Some(states) if states.is_empty()) {
+                    idle.states.remove(options);
+                }
+            }
+
+            state
+        };
+
+        match reused {
+            Some(state) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                state
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                T::create(options)
+            }
+        }
+    }
+
+    fn put(&self, mut state: T) {
+        // Reset outside of the lock; a state which fails to reset is dropped.
+        if !state.reset() {
+            return;
+        }
+
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len < self.max_idle {
+            idle.len += 1;
+
+            // Avoid cloning the options, and their seed bytes, if the key already exists.
+            match idle.states.get_mut(state.options()) {
+                Some(states) => states.push(state),
+                None => {
+                    idle.states.insert(state.options().clone(), vec![state]);
+                }
+            }
+        }
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            idle: self.idle.lock().unwrap_or_else(|e| e.into_inner()).len,
+        }
+    }
+}
+
+impl<T: Reusable> fmt::Debug for Pool<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("max_idle", &self.max_idle)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
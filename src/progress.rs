@@ -0,0 +1,136 @@
+//! Progress reporting and cancellation of long running operations.
+//!
+//! [`Compressor::compress_with_progress`] and [`Decompressor::decompress_with_progress`]
+//! call a callback with the current [`Progress`] after every iteration of the native codec,
+//! and check a [`CancellationToken`] before every iteration.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::{compress::Compressor, progress::CancellationToken, CompressionOptions};
+//! let data = vec![0; 1 << 20];
+//! let token = CancellationToken::new();
+//!
+//! // The token can be cancelled from another thread, for example from a GUI.
+//! let handle = token.clone();
+//!
+//! let mut compressor = Compressor::from_options(CompressionOptions::default());
+//! let mut comp = Vec::new();
+//! let result = compressor.compress_with_progress(&mut data.as_slice(), &mut comp, &token, |p| {
+//!     println!("{} of {} bytes", p.input_consumed, data.len());
+//! });
+//!
+//! assert!(result.is_ok());
+//! ```
+//!
+//! [`Compressor::compress_with_progress`]: crate::compress::Compressor::compress_with_progress
+//! [`Decompressor::decompress_with_progress`]: crate::decompress::Decompressor::decompress_with_progress
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+/// The progress of a compression or decompression.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Progress {
+    /// The number of bytes consumed from the input so far.
+    pub input_consumed: u64,
+    /// The number of bytes written to the output so far.
+    pub output_produced: u64,
+    /// The time elapsed since the operation started.
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    /// Estimates the time remaining until `total_input` bytes are consumed.
+    ///
+    /// The estimate assumes the rest of the input is consumed at the average rate so far.
+    /// Returns `None` if no input has been consumed yet.
+    pub fn estimate_remaining(&self, total_input: u64) -> Option<Duration> {
+        if self.input_consumed == 0 {
+            return None;
+        }
+
+        let remaining = total_input.saturating_sub(self.input_consumed);
+
+        Some(
+            self.elapsed
+                .mul_f64(remaining as f64 / self.input_consumed as f64),
+        )
+    }
+}
+
+/// A token to cancel a running compression or decompression.
+///
+/// Clones of a token share its state, so cancelling any clone cancels the operations using
+/// all of them.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the operations using the token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps track of the progress of an operation.
+#[derive(Debug)]
+pub(crate) struct Tracker {
+    start: Instant,
+    progress: Progress,
+    iterations: u64,
+    buffer_growths: u64,
+}
+
+impl Tracker {
+    pub(crate) fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            progress: Progress::default(),
+            iterations: 0,
+            buffer_growths: 0,
+        }
+    }
+
+    /// Records an iteration of the native codec and returns the updated progress.
+    pub(crate) fn update(&mut self, read: usize, written: usize) -> &Progress {
+        self.progress.input_consumed += read as u64;
+        self.progress.output_produced += written as u64;
+        self.progress.elapsed = self.start.elapsed();
+        self.iterations += 1;
+
+        &self.progress
+    }
+
+    /// Records a growth of the output buffer.
+    pub(crate) fn grow(&mut self) {
+        self.buffer_growths += 1;
+    }
+
+    pub(crate) fn progress(&self) -> &Progress {
+        &self.progress
+    }
+
+    pub(crate) fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    pub(crate) fn buffer_growths(&self) -> u64 {
+        self.buffer_growths
+    }
+}
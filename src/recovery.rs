@@ -0,0 +1,403 @@
+//! Error-resilient decompression of streams with full flush points.
+//!
+//! A [full flush] resets the state of the compressor, so the data after it does not depend
+//! on the data before it. [`decompress_recovering`] uses this to recover from corruption:
+//! when a part of the stream between two full flush points fails to decode, it skips it,
+//! resets the decompressor and resumes decoding at the next full flush point. The skipped
+//! parts are returned as [`DamagedRange`]s.
+//!
+//! Streams with periodic full flush points can be produced with
+//! [`compress_with_flush_points`].
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::recovery::{compress_with_flush_points, decompress_recovering};
+//! let data = vec![0; 1 << 20];
+//!
+//! let mut comp = Vec::new();
+//! compress_with_flush_points(&mut data.as_slice(), &mut comp, &Default::default(), 1 << 16)
+//!     .unwrap();
+//!
+//! let mut decomp = Vec::new();
+//! let report =
+//!     decompress_recovering(&mut comp.as_slice(), &mut decomp, 1 << 16, &Default::default())
+//!         .unwrap();
+//!
+//! assert!(report.damaged.is_empty());
+//! ```
+//!
+//! [full flush]: crate::compress::CompressionFlush::Full
+
+use crate::{
+    compress::{compress_bound, CompressionFlush, Compressor},
+    decompress::{DecompressionStatus, Decompressor},
+    low::BUF_SIZE,
+    CompressionOptions, DecompressionOptions, Error, Result,
+};
+use std::{
+    io::{BufRead, Write},
+    ops::Range,
+};
+
+/// The bytes ending the sync block of a full flush.
+///
+/// The block is byte-aligned, and made of the block type and flush type bits, followed by
+/// `0x0000` and `0xFFFF`.
+const FULL_FLUSH_MARKER: [u8; 5] = [0x20, 0x00, 0x00, 0xFF, 0xFF];
+
+/// A part of a compressed stream which could not be decoded.
+#[derive(Clone, Debug)]
+pub struct DamagedRange {
+    /// The compressed bytes between the two full flush points around the damage.
+    pub compressed: Range<u64>,
+    /// The offset of the decompressed output at which the data of the range is missing.
+    pub uncompressed_offset: u64,
+    /// The status the codec failed with, if it detected the damage.
+    ///
+    /// It is `None` if the range decoded to the wrong amount of data, or if the stream is
+    /// truncated.
+    pub status: Option<DecompressionStatus>,
+}
+
+/// The result of [`decompress_recovering`].
+#[derive(Clone, Debug, Default)]
+pub struct RecoveryReport {
+    /// The damaged parts of the stream, in order.
+    pub damaged: Vec<DamagedRange>,
+    /// The number of compressed bytes read.
+    pub bytes_in: u64,
+    /// The number of decompressed bytes written.
+    pub bytes_out: u64,
+    /// Whether the end of the stream was decoded.
+    ///
+    /// It is false if the stream is truncated or damaged after its last full flush point.
+    pub finished: bool,
+}
+
+/// Compresses input data into the output buffer with provided [`options`], emitting a full
+/// flush after every `flush_interval` bytes of input.
+///
+/// Every full flush costs a few bytes and some ratio, as the data after it cannot match
+/// against the data before it. If `flush_interval` is zero, no flush is emitted.
+///
+/// [`options`]: CompressionOptions
+pub fn compress_with_flush_points<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    options: &CompressionOptions,
+    flush_interval: usize,
+) -> Result<()> {
+    let flush_interval = if flush_interval == 0 {
+        usize::MAX
+    } else {
+        flush_interval
+    };
+
+    let mut compressor = Compressor::from_options(options.clone());
+    let mut out_buf = [0; BUF_SIZE];
+    let mut until_flush = flush_interval;
+
+    loop {
+        let in_buf = input.fill_buf()?;
+        let (chunk, flush) = if in_buf.is_empty() {
+            (in_buf, CompressionFlush::Finish)
+        } else if in_buf.len() >= until_flush {
+            // The flush is applied by the call which consumes the whole chunk.
+            (&in_buf[..until_flush], CompressionFlush::Full)
+        } else {
+            (in_buf, CompressionFlush::None)
+        };
+
+        let (status, read, written) = compressor.compress_buf(chunk, &mut out_buf, flush);
+
+        input.consume(read);
+        output.write_all(&out_buf[..written])?;
+
+        until_flush -= read;
+        if until_flush == 0 {
+            until_flush = flush_interval;
+        }
+
+        if status.is_success() {
+            return Ok(());
+        } else if status.is_success_or_first_failure() {
+            return Err(Error::Compression(status));
+        }
+    }
+}
+
+/// Decompresses input data compressed by [`compress_with_flush_points`] into the output
+/// buffer with provided [`options`], skipping the damaged parts of the stream.
+///
+/// The codec does not reliably detect corruption: damaged data can decode to garbage, or
+/// even to an early end of stream. So the stream is decoded one full flush point at a time,
+/// and every part must decode to exactly `flush_interval` bytes, the interval it was
+/// compressed with. Only the parts which do are written to the output. When one does not,
+/// the input is skipped to the next full flush point, where decoding resumes with a reset
+/// decompressor. If the stream was compressed with [`DecompressionFlag::ComputeAdler32`],
+/// the checksum mismatch at the end of a damaged stream is ignored.
+///
+/// The bytes of a full flush point may also appear in data stored uncompressed. A part only
+/// ends at them once it has failed or decoded to the whole interval. Otherwise decoding goes
+/// on, and resumes after them if the part turns out to be damaged.
+///
+/// Up to `flush_interval` bytes of output are buffered, and the compressed input following
+/// such bytes until the part ends.
+///
+/// # Panics
+///
+/// Panics if `flush_interval` is zero.
+///
+/// [`options`]: DecompressionOptions
+/// [`DecompressionFlag::ComputeAdler32`]: crate::decompress::DecompressionFlag::ComputeAdler32
+pub fn decompress_recovering<R: BufRead, W: Write>(
+    input: &mut R,
+    output: &mut W,
+    flush_interval: usize,
+    options: &DecompressionOptions,
+) -> Result<RecoveryReport> {
+    assert!(flush_interval > 0, "the flush interval must be non-zero");
+
+    // An intact part is never larger than the bound of its data.
+    let max_compressed = compress_bound(
+        flush_interval,
+        &CompressionOptions {
+            dict_size_log2: options.dict_size_log2,
+            ..Default::default()
+        },
+    ) as u64;
+
+    let mut decompressor = Decompressor::from_options(options.clone());
+    let mut report = RecoveryReport::default();
+    let mut segment = Segment::new(flush_interval);
+    let mut matched = 0;
+    // The offset after the first marker which did not end the segment, and the input read
+    // since, to resume from if the segment turns out to be damaged.
+    let mut fallback = None;
+    let mut recorded = Vec::new();
+    let mut replay = Vec::new();
+    let mut replay_pos = 0;
+
+    loop {
+        let replaying = replay_pos < replay.len();
+        let in_buf = if replaying {
+            &replay[replay_pos..]
+        } else {
+            input.fill_buf()?
+        };
+        let no_more_input = in_buf.is_empty();
+        let (len, marker) = find_flush_point(in_buf, &mut matched);
+
+        let read = segment.decode(&mut decompressor, &in_buf[..len], no_more_input);
+        let finished = match segment.state {
+            State::Finished => true,
+            // The checksum covers the skipped data.
+            State::Failed(Some(DecompressionStatus::Adler32)) => !report.damaged.is_empty(),
+            _ => false,
+        };
+
+        // The rest of the input is not part of a finished stream.
+        let read = if finished { read } else { len };
+        if fallback.is_some() {
+            recorded.extend_from_slice(&in_buf[..read]);
+        }
+        if replaying {
+            replay_pos += read;
+        } else {
+            input.consume(read);
+        }
+        report.bytes_in += read as u64;
+
+        if matches!(segment.state, State::Decoding)
+            && report.bytes_in - segment.start > max_compressed
+        {
+            segment.state = State::Failed(None);
+        }
+
+        // The marker may also be part of the data of a raw block, so it only ends a segment
+        // which failed or decoded to the whole interval.
+        let at_flush_point = marker
+            && match segment.state {
+                State::Failed(_) => true,
+                _ => segment.out.len() == flush_interval,
+            };
+
+        let failed = match segment.state {
+            State::Failed(status) if !finished => Some(status),
+            State::Decoding if no_more_input => Some(None),
+            _ => None,
+        };
+
+        match (fallback, failed) {
+            (Some(offset), Some(status)) => {
+                resume(
+                    &mut report,
+                    &mut decompressor,
+                    segment.start..offset,
+                    status,
+                )?;
+
+                // The rest of the replayed input follows the recorded one.
+                recorded.extend_from_slice(&replay[replay_pos..]);
+                replay = std::mem::take(&mut recorded);
+                replay_pos = 0;
+                report.bytes_in = offset;
+                matched = 0;
+                fallback = None;
+                segment.restart(offset);
+                continue;
+            }
+            (None, None) if marker && !at_flush_point => fallback = Some(report.bytes_in),
+            _ => {}
+        }
+
+        if !(finished || at_flush_point || no_more_input) {
+            continue;
+        }
+
+        let damage = match segment.state {
+            _ if finished => None,
+            State::Failed(status) => Some(status),
+            // The stream is truncated, or the segment decoded to the wrong amount of data.
+            _ if no_more_input || segment.out.len() != flush_interval => Some(None),
+            _ => None,
+        };
+
+        if let Some(status) = damage {
+            let compressed = segment.start..report.bytes_in;
+            resume(&mut report, &mut decompressor, compressed, status)?;
+        } else {
+            output.write_all(&segment.out)?;
+            report.bytes_out += segment.out.len() as u64;
+        }
+
+        if finished || no_more_input {
+            report.finished = finished;
+            return Ok(report);
+        }
+
+        fallback = None;
+        recorded.clear();
+        segment.restart(report.bytes_in);
+    }
+}
+
+/// Records a damaged range, and resets the decompressor to resume after it.
+fn resume(
+    report: &mut RecoveryReport,
+    decompressor: &mut Decompressor,
+    compressed: Range<u64>,
+    status: Option<DecompressionStatus>,
+) -> Result<()> {
+    report.damaged.push(DamagedRange {
+        compressed,
+        uncompressed_offset: report.bytes_out,
+        status,
+    });
+
+    if decompressor.reset() {
+        Ok(())
+    } else {
+        Err(Error::Decompression(
+            DecompressionStatus::FailedInitialization,
+        ))
+    }
+}
+
+/// The decoding state of the part of a stream between two full flush points.
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Decoding,
+    Finished,
+    /// The segment is damaged, with the status the codec failed with if it detected it.
+    Failed(Option<DecompressionStatus>),
+}
+
+/// The part of a stream between two full flush points.
+struct Segment {
+    out: Vec<u8>,
+    out_buf: Box<[u8]>,
+    /// The largest valid output, plus one byte to detect overflows.
+    limit: usize,
+    /// The offset of the compressed data of the segment.
+    start: u64,
+    state: State,
+}
+
+impl Segment {
+    fn new(flush_interval: usize) -> Self {
+        Self {
+            out: Vec::new(),
+            out_buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            limit: flush_interval.saturating_add(1),
+            start: 0,
+            state: State::Decoding,
+        }
+    }
+
+    fn restart(&mut self, start: u64) {
+        self.out.clear();
+        self.start = start;
+        self.state = State::Decoding;
+    }
+
+    /// Decodes input data of the segment, and returns the number of bytes read.
+    ///
+    /// Once the segment is finished or failed, the input is ignored.
+    fn decode(
+        &mut self,
+        decompressor: &mut Decompressor,
+        mut input: &[u8],
+        no_more_input: bool,
+    ) -> usize {
+        let len = input.len();
+
+        while matches!(self.state, State::Decoding) {
+            let avail = (self.limit - self.out.len()).min(BUF_SIZE);
+            let (status, read, written) =
+                decompressor.decompress_buf(input, &mut self.out_buf[..avail], no_more_input);
+
+            self.out.extend_from_slice(&self.out_buf[..written]);
+            input = &input[read..];
+
+            self.state = match status {
+                DecompressionStatus::Success => State::Finished,
+                status if status.is_success_or_first_failure() => State::Failed(Some(status)),
+                // The segment decoded to more data than the interval.
+                _ if self.out.len() == self.limit => State::Failed(None),
+                DecompressionStatus::NeedsMoreInput if input.is_empty() => break,
+                _ if read == 0 && written == 0 => State::Failed(None),
+                _ => State::Decoding,
+            };
+        }
+
+        len - input.len()
+    }
+}
+
+/// Finds the end of the next full flush marker in `buf`.
+///
+/// Returns the number of bytes up to the end of the marker and true if it is found, or the
+/// length of `buf` and false otherwise. `matched` holds the length of the partial match
+/// across calls.
+fn find_flush_point(buf: &[u8], matched: &mut usize) -> (usize, bool) {
+    for (i, &byte) in buf.iter().enumerate() {
+        // No proper prefix of the marker is also its suffix, so a mismatch can only restart
+        // a match at the current byte.
+        *matched = if byte == FULL_FLUSH_MARKER[*matched] {
+            *matched + 1
+        } else if byte == FULL_FLUSH_MARKER[0] {
+            1
+        } else {
+            0
+        };
+
+        if *matched == FULL_FLUSH_MARKER.len() {
+            *matched = 0;
+            return (i + 1, true);
+        }
+    }
+
+    (buf.len(), false)
+}
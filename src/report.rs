@@ -0,0 +1,168 @@
+//! Statistics about finished compressions and decompressions.
+//!
+//! The `_with_report` functions and methods return a [`CompressionReport`] or a
+//! [`DecompressionReport`], which can be logged to track the performance of the codec.
+
+use crate::{
+    compress::{CompressionLevel, CompressionOptions},
+    decompress::DecompressionOptions,
+    progress::Tracker,
+    TableUpdateInterval, TableUpdateRate,
+};
+use std::time::Duration;
+
+/// Statistics about a finished compression.
+#[derive(Clone, Debug)]
+pub struct CompressionReport {
+    /// The number of uncompressed bytes read.
+    pub bytes_in: u64,
+    /// The number of compressed bytes written.
+    pub bytes_out: u64,
+    /// The wall time of the compression.
+    pub elapsed: Duration,
+    /// The number of calls to the native codec.
+    pub iterations: u64,
+    /// The number of times the output buffer had to grow.
+    ///
+    /// It is always zero when compressing into a writer, as the output goes through a
+    /// fixed-size buffer.
+    pub buffer_growths: u64,
+    /// The parameters used by the native codec.
+    pub params: CompressionParams,
+}
+
+/// Statistics about a finished decompression.
+#[derive(Clone, Debug)]
+pub struct DecompressionReport {
+    /// The number of compressed bytes read.
+    ///
+    /// It is the exact size of the stream, as the data following it is not read.
+    pub bytes_in: u64,
+    /// The number of decompressed bytes written.
+    pub bytes_out: u64,
+    /// The wall time of the decompression.
+    pub elapsed: Duration,
+    /// The number of calls to the native codec.
+    pub iterations: u64,
+    /// The number of times the output buffer had to grow.
+    ///
+    /// It is always zero when decompressing into a writer, as the output goes through a
+    /// fixed-size buffer.
+    pub buffer_growths: u64,
+    /// The parameters used by the native codec.
+    pub params: DecompressionParams,
+}
+
+/// The effective parameters of a compression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionParams {
+    /// The base 2 log of the dictionary size.
+    pub dict_size_log2: u32,
+    /// The level of compression.
+    pub compression_level: CompressionLevel,
+    /// The table update rate.
+    pub table_update_rate: TableUpdateRate,
+    /// The table update interval, either specified or the default one.
+    pub table_update_interval: TableUpdateInterval,
+    /// The maximum number of helper threads.
+    pub max_helper_threads: i32,
+    /// The number of seed bytes loaded.
+    pub num_seed_bytes: u32,
+}
+
+/// The effective parameters of a decompression.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DecompressionParams {
+    /// The base 2 log of the dictionary size.
+    pub dict_size_log2: u32,
+    /// The table update rate.
+    pub table_update_rate: TableUpdateRate,
+    /// The table update interval, either specified or the default one.
+    pub table_update_interval: TableUpdateInterval,
+    /// The number of seed bytes loaded.
+    pub num_seed_bytes: u32,
+}
+
+impl CompressionReport {
+    pub(crate) fn new(tracker: &Tracker, options: &CompressionOptions) -> Self {
+        let progress = tracker.progress();
+
+        Self {
+            bytes_in: progress.input_consumed,
+            bytes_out: progress.output_produced,
+            elapsed: progress.elapsed,
+            iterations: tracker.iterations(),
+            buffer_growths: tracker.buffer_growths(),
+            params: CompressionParams {
+                dict_size_log2: options.dict_size_log2,
+                compression_level: options.compression_level,
+                table_update_rate: options.table_update_rate,
+                table_update_interval: options.effective_table_update_interval(),
+                max_helper_threads: options.max_helper_threads,
+                num_seed_bytes: options.effective_num_seed_bytes() as u32,
+            },
+        }
+    }
+
+    /// Returns the compressed size divided by the uncompressed size.
+    ///
+    /// Returns `0.0` if there was no input.
+    pub fn ratio(&self) -> f64 {
+        ratio(self.bytes_out, self.bytes_in)
+    }
+
+    /// Returns the number of uncompressed bytes processed per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes_in, self.elapsed)
+    }
+}
+
+impl DecompressionReport {
+    pub(crate) fn new(tracker: &Tracker, options: &DecompressionOptions) -> Self {
+        let progress = tracker.progress();
+
+        Self {
+            bytes_in: progress.input_consumed,
+            bytes_out: progress.output_produced,
+            elapsed: progress.elapsed,
+            iterations: tracker.iterations(),
+            buffer_growths: tracker.buffer_growths(),
+            params: DecompressionParams {
+                dict_size_log2: options.dict_size_log2,
+                table_update_rate: options.table_update_rate,
+                table_update_interval: options.effective_table_update_interval(),
+                num_seed_bytes: options.effective_num_seed_bytes() as u32,
+            },
+        }
+    }
+
+    /// Returns the compressed size divided by the decompressed size.
+    ///
+    /// Returns `0.0` if there was no output.
+    pub fn ratio(&self) -> f64 {
+        ratio(self.bytes_in, self.bytes_out)
+    }
+
+    /// Returns the number of decompressed bytes produced per second.
+    pub fn throughput(&self) -> f64 {
+        throughput(self.bytes_out, self.elapsed)
+    }
+}
+
+fn ratio(compressed: u64, uncompressed: u64) -> f64 {
+    if uncompressed == 0 {
+        0.0
+    } else {
+        compressed as f64 / uncompressed as f64
+    }
+}
+
+fn throughput(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+
+    if secs > 0.0 {
+        bytes as f64 / secs
+    } else {
+        0.0
+    }
+}
@@ -0,0 +1,276 @@
+//! Compression of both directions of a duplex byte stream, like a [`TcpStream`].
+//!
+//! [`CompressedStream`] compresses the data written to it and decompresses the data read
+//! from it, with an independent LZHAM stream in each direction. Both peers of a connection
+//! wrap their end, and must agree on the options.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use lzham::stream::CompressedStream;
+//! # use std::{io::{Read, Write}, net::TcpStream};
+//! let mut stream = CompressedStream::new(TcpStream::connect("127.0.0.1:4000").unwrap());
+//!
+//! stream.write_all(b"This is a request.").unwrap();
+//! stream.flush().unwrap();
+//!
+//! let mut response = [0; 19];
+//! stream.read_exact(&mut response).unwrap();
+//!
+//! println!("compression ratio: {}", stream.write_stats().ratio());
+//! ```
+//!
+//! [`TcpStream`]: std::net::TcpStream
+
+use crate::{
+    compress::{CompressionFlush, Encoder},
+    decompress::Decompressor,
+    low::BUF_SIZE,
+    CompressionOptions, DecompressionOptions, Error,
+};
+use std::io::{self, Read, Write};
+
+/// The number of bytes which went through one direction of a [`CompressedStream`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// The number of compressed bytes, on the side of the inner stream.
+    pub compressed: u64,
+    /// The number of uncompressed bytes, on the side of the application.
+    pub uncompressed: u64,
+}
+
+impl StreamStats {
+    /// Returns the compressed size divided by the uncompressed size.
+    ///
+    /// Returns `0.0` if no data went through.
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed == 0 {
+            0.0
+        } else {
+            self.compressed as f64 / self.uncompressed as f64
+        }
+    }
+}
+
+/// A duplex stream compressing the data written to it and decompressing the data read from
+/// it.
+///
+/// Written data is compressed into the inner stream as it goes, but the codec holds some of
+/// it back. [`flush`] emits a sync flush, after which the peer can read all the data written
+/// so far, so it must be called before waiting for a response. [`finish`] ends the outgoing
+/// stream, and reading returns end of file once the incoming stream is finished.
+///
+/// [`flush`]: Write::flush
+/// [`finish`]: CompressedStream::finish
+#[derive(Debug)]
+pub struct CompressedStream<S> {
+    inner: S,
+    encoder: Encoder,
+    decompressor: Decompressor,
+    in_buf: Box<[u8]>,
+    in_pos: usize,
+    in_len: usize,
+    // Whether the last call to the decompressor produced output, so it may have more.
+    read_pending: bool,
+    // Whether data was written since the last flush.
+    dirty: bool,
+    // Whether the outgoing stream is finished.
+    write_done: bool,
+    // Whether the incoming stream is finished.
+    read_done: bool,
+    write_stats: StreamStats,
+    read_stats: StreamStats,
+}
+
+impl<S: Read + Write> CompressedStream<S> {
+    /// Creates a new stream with default options.
+    pub fn new(inner: S) -> Self {
+        Self::with_options(
+            inner,
+            CompressionOptions::default(),
+            DecompressionOptions::default(),
+        )
+    }
+
+    /// Creates a new stream with provided [`compression`] and [`decompression`] options.
+    ///
+    /// The compression options apply to the written data, and the decompression options to
+    /// the read data, so they must match the options of the peer the other way around.
+    ///
+    /// [`compression`]: CompressionOptions
+    /// [`decompression`]: DecompressionOptions
+    pub fn with_options(
+        inner: S,
+        compression: CompressionOptions,
+        decompression: DecompressionOptions,
+    ) -> Self {
+        Self {
+            inner,
+            encoder: Encoder::new(compression),
+            decompressor: Decompressor::from_options(decompression),
+            in_buf: vec![0; BUF_SIZE].into_boxed_slice(),
+            in_pos: 0,
+            in_len: 0,
+            read_pending: false,
+            dirty: false,
+            write_done: false,
+            read_done: false,
+            write_stats: StreamStats::default(),
+            read_stats: StreamStats::default(),
+        }
+    }
+
+    /// Returns a reference to the inner stream.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner stream.
+    ///
+    /// Reading from it or writing to it directly corrupts the compressed streams.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes the stream and returns the inner stream.
+    ///
+    /// The outgoing stream is incomplete unless it was finished, and the buffered incoming
+    /// data is lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns the statistics of the written data.
+    pub fn write_stats(&self) -> StreamStats {
+        self.write_stats
+    }
+
+    /// Returns the statistics of the read data.
+    pub fn read_stats(&self) -> StreamStats {
+        self.read_stats
+    }
+
+    /// Finishes the outgoing stream, and flushes the inner stream.
+    ///
+    /// Writing afterwards fails. Reading is still possible, and the inner stream is not
+    /// shut down.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if !self.write_done {
+            self.compress_flush(CompressionFlush::Finish)?;
+            self.write_done = true;
+        }
+
+        self.inner.flush()
+    }
+
+    /// Passes `flush` to the codec and writes all the output to the inner stream.
+    fn compress_flush(&mut self, flush: CompressionFlush) -> io::Result<()> {
+        loop {
+            let complete = self.encoder.flush(flush)?;
+            self.write_output()?;
+
+            if complete {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Writes the output of the last call to the codec to the inner stream.
+    fn write_output(&mut self) -> io::Result<()> {
+        let output = self.encoder.output();
+        self.inner.write_all(output)?;
+        self.write_stats.compressed += output.len() as u64;
+
+        Ok(())
+    }
+
+    /// Reads more compressed data from the inner stream, and returns the number of bytes
+    /// read.
+    fn fill(&mut self) -> io::Result<usize> {
+        if self.in_pos == self.in_len {
+            self.in_pos = 0;
+            self.in_len = 0;
+        } else if self.in_len == self.in_buf.len() {
+            self.in_buf.copy_within(self.in_pos..self.in_len, 0);
+            self.in_len -= self.in_pos;
+            self.in_pos = 0;
+        }
+
+        let read = self.inner.read(&mut self.in_buf[self.in_len..])?;
+        self.in_len += read;
+
+        Ok(read)
+    }
+}
+
+impl<S: Read + Write> Read for CompressedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.read_done || buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            // The codec may hold output from previous input, so it runs before blocking on
+            // the inner stream. It must not be called without input otherwise.
+            if self.in_pos == self.in_len && !self.read_pending && self.fill()? == 0 {
+                // A peer which closes the connection without writing is not an error.
+                return if self.read_stats.compressed == 0 {
+                    Ok(0)
+                } else {
+                    Err(io::ErrorKind::UnexpectedEof.into())
+                };
+            }
+
+            let (status, read, written) = self.decompressor.decompress_buf(
+                &self.in_buf[self.in_pos..self.in_len],
+                buf,
+                false,
+            );
+            self.read_pending = written > 0;
+            self.in_pos += read;
+            self.read_stats.compressed += read as u64;
+            self.read_stats.uncompressed += written as u64;
+
+            if status.is_success() {
+                self.read_done = true;
+                return Ok(written);
+            } else if status.is_success_or_first_failure() {
+                return Err(Error::Decompression(status).into());
+            } else if written > 0 {
+                return Ok(written);
+            } else if read == 0 && self.fill()? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Write for CompressedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.write_done {
+            return Err(io::Error::new(io::ErrorKind::Other, "write after finish"));
+        } else if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let read = self.encoder.compress(buf)?;
+            self.write_output()?;
+
+            if read > 0 {
+                self.write_stats.uncompressed += read as u64;
+                self.dirty = true;
+                return Ok(read);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.dirty && !self.write_done {
+            self.compress_flush(CompressionFlush::Sync)?;
+            self.dirty = false;
+        }
+
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,237 @@
+//! Compressed tar archives of directory trees, built on the [`tar`] crate.
+//!
+//! [`pack_dir`] writes a directory tree as a tar archive compressed into a single LZHAM
+//! stream, the equivalent of a `.tar.lzham` file, and [`unpack`] extracts it. Both stream
+//! the data through the codec, so the archive is never held in memory. Permissions and
+//! modification times are kept.
+//!
+//! This module requires the `tar` feature.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # use std::{fs::File, io::BufReader};
+//! let file = File::create("assets.tar.lzham").unwrap();
+//! lzham::tar::pack_dir("assets", file, &Default::default()).unwrap();
+//!
+//! let file = BufReader::new(File::open("assets.tar.lzham").unwrap());
+//! lzham::tar::unpack(file, "assets-copy").unwrap();
+//! ```
+//!
+//! [`tar`]: ::tar
+
+use crate::{
+    compress::{CompressionFlush, Encoder},
+    decompress::Decoder,
+    CompressionOptions, DecompressionOptions,
+};
+use ::tar::{Archive, Builder, EntryType, HeaderMode};
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Component, Path, PathBuf},
+};
+
+/// Writes the directory tree at `path` into `writer` as a compressed tar archive, with
+/// provided [`options`], and returns the writer.
+///
+/// The paths in the archive are relative to `path`. Symbolic links are stored as links.
+///
+/// [`options`]: CompressionOptions
+pub fn pack_dir<P: AsRef<Path>, W: Write>(
+    path: P,
+    writer: W,
+    options: &CompressionOptions,
+) -> io::Result<W> {
+    let mut builder = Builder::new(Writer::new(writer, options.clone()));
+    builder.mode(HeaderMode::Complete);
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", path)?;
+
+    builder.into_inner()?.finish()
+}
+
+/// Extracts a compressed tar archive read from `reader` into the `dest` directory, with
+/// default [`options`].
+///
+/// See [`unpack_with_options`] for details.
+///
+/// [`options`]: DecompressionOptions
+pub fn unpack<R: BufRead, P: AsRef<Path>>(reader: R, dest: P) -> io::Result<()> {
+    unpack_with_options(reader, dest, &DecompressionOptions::default())
+}
+
+/// Extracts a compressed tar archive read from `reader` into the `dest` directory, with
+/// provided [`options`].
+///
+/// `dest` is created if needed, and existing files are overwritten. Permissions and
+/// modification times are restored. It fails with [`io::ErrorKind::InvalidData`] if an
+/// entry, or the target of a link, would be written outside of `dest`. The entries before
+/// it are already extracted.
+///
+/// [`options`]: DecompressionOptions
+pub fn unpack_with_options<R: BufRead, P: AsRef<Path>>(
+    reader: R,
+    dest: P,
+    options: &DecompressionOptions,
+) -> io::Result<()> {
+    let dest = dest.as_ref();
+    fs::create_dir_all(dest)?;
+    let canonical_dest = fs::canonicalize(dest)?;
+
+    let mut archive = Archive::new(Decoder::new(reader, options.clone()));
+    archive.set_preserve_permissions(true);
+    archive.set_preserve_mtime(true);
+    archive.set_overwrite(true);
+
+    // Directories are extracted last, so extracting their content does not change their
+    // modification time, and read-only directories can still be filled.
+    let mut directories = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        if !is_inside(&path) {
+            return Err(traversal(&path));
+        }
+
+        match entry.header().entry_type() {
+            EntryType::Symlink => {
+                // A relative target is resolved from the directory of the link, following
+                // the links already extracted.
+                let target = entry.link_name()?.unwrap_or_default();
+                let parent = dest.join(path.parent().unwrap_or(&path));
+                if target.is_absolute()
+                    || !resolve(&parent.join(target)).starts_with(&canonical_dest)
+                {
+                    return Err(traversal(&path));
+                }
+            }
+            EntryType::Link => {
+                let target = entry.link_name()?.unwrap_or_default();
+                if !is_inside(&target) || !resolve(&dest.join(target)).starts_with(&canonical_dest)
+                {
+                    return Err(traversal(&path));
+                }
+            }
+            _ => {}
+        }
+
+        if entry.header().entry_type() == EntryType::Directory {
+            directories.push(entry);
+        } else if !entry.unpack_in(dest)? {
+            return Err(traversal(&path));
+        }
+    }
+
+    for mut directory in directories {
+        directory.unpack_in(dest)?;
+    }
+
+    // The end of the tar archive is not the end of the stream, which must be complete.
+    io::copy(&mut archive.into_inner(), &mut io::sink())?;
+
+    Ok(())
+}
+
+/// Returns whether the relative `path` stays inside the directory it is relative to.
+fn is_inside(path: &Path) -> bool {
+    let mut depth = 0usize;
+
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+
+    true
+}
+
+/// Resolves `path` like the file system does, following the links in its existing part.
+fn resolve(path: &Path) -> PathBuf {
+    let mut resolved = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            // The resolved path is canonical while it exists, so its parent is its last
+            // component removed.
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::CurDir => {}
+            component => {
+                resolved.push(component);
+                if let Ok(canonical) = fs::canonicalize(&resolved) {
+                    resolved = canonical;
+                }
+            }
+        }
+    }
+
+    resolved
+}
+
+fn traversal(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("{} is outside of the destination", path.display()),
+    )
+}
+
+/// Compresses the data written to it into a [`Write`].
+struct Writer<W: Write> {
+    inner: W,
+    encoder: Encoder,
+}
+
+impl<W: Write> Writer<W> {
+    fn new(inner: W, options: CompressionOptions) -> Self {
+        Self {
+            inner,
+            encoder: Encoder::new(options),
+        }
+    }
+
+    /// Finishes the stream and returns the inner writer.
+    fn finish(mut self) -> io::Result<W> {
+        loop {
+            let complete = self.encoder.flush(CompressionFlush::Finish)?;
+            self.inner.write_all(self.encoder.output())?;
+
+            if complete {
+                break;
+            }
+        }
+
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for Writer<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        loop {
+            let read = self.encoder.compress(buf)?;
+            self.inner.write_all(self.encoder.output())?;
+
+            if read > 0 {
+                return Ok(read);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
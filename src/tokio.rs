@@ -0,0 +1,50 @@
+//! Asynchronous compression and decompression with [`tokio`].
+//!
+//! [`LzhamEncoder`] compresses the data written to it into an [`AsyncWrite`], and
+//! [`LzhamDecoder`] decompresses the data read from an [`AsyncBufRead`]. Both drive the
+//! native codec incrementally, and yield back to the executor after a bounded amount of
+//! codec work, so a large stream does not starve the other tasks.
+//!
+//! With the `tokio-util` feature, the `codec` module also provides a codec for
+//! compressed frames.
+//!
+//! This module requires the `tokio` feature.
+//!
+//! ## Examples
+//!
+//! ```no_run
+//! # async fn run() -> std::io::Result<()> {
+//! use lzham::tokio::{LzhamDecoder, LzhamEncoder};
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! let mut encoder = LzhamEncoder::new(Vec::new());
+//! encoder.write_all(b"This is a test.").await?;
+//! encoder.shutdown().await?;
+//!
+//! let comp = encoder.into_inner();
+//!
+//! let mut decoder = LzhamDecoder::new(comp.as_slice());
+//! let mut decomp = Vec::new();
+//! decoder.read_to_end(&mut decomp).await?;
+//!
+//! assert_eq!(decomp, b"This is a test.");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`AsyncWrite`]: ::tokio::io::AsyncWrite
+//! [`AsyncBufRead`]: ::tokio::io::AsyncBufRead
+
+#[cfg(feature = "tokio-util")]
+pub mod codec;
+mod decoder;
+mod encoder;
+
+pub use decoder::LzhamDecoder;
+pub use encoder::LzhamEncoder;
+
+/// The largest number of input bytes passed to the native codec in a single call.
+const IN_CHUNK_SIZE: usize = 1 << 16;
+
+/// The largest number of calls to the native codec in a single poll.
+const CODEC_CALLS_PER_POLL: usize = 8;
@@ -0,0 +1,210 @@
+//! A [`tokio_util::codec`] for LZHAM-compressed frames.
+//!
+//! Every frame starts with an 8 bytes header, which holds the length of the compressed
+//! payload and the length of the uncompressed data, both as big-endian 32-bit integers. The
+//! payload is a complete LZHAM stream, so frames can be decoded independently.
+//!
+//! This module requires the `tokio-util` feature.
+
+use crate::{
+    compress::{CompressionFlush, Compressor},
+    compress_bound,
+    decompress::Decompressor,
+    CompressionOptions, DecompressionOptions, Error,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+use tokio_util::codec::{Decoder, Encoder};
+
+/// The size of the frame header.
+const HEADER_SIZE: usize = 8;
+
+/// The default maximum size of the uncompressed data of a frame.
+const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+/// Compresses and decompresses [`Bytes`] frames.
+///
+/// The codec keeps one [`Compressor`] and one [`Decompressor`], which are reinitialized
+/// after every frame. The same seed dictionary must be passed to both sides of a
+/// connection through the options.
+#[derive(Debug)]
+pub struct LzhamCodec {
+    compressor: Compressor,
+    decompressor: Decompressor,
+    max_frame_size: usize,
+}
+
+impl LzhamCodec {
+    /// Creates a new codec with default options.
+    pub fn new() -> Self {
+        Self::with_options(
+            CompressionOptions::default(),
+            DecompressionOptions::default(),
+        )
+    }
+
+    /// Creates a new codec with provided [`compression`] and [`decompression`] options.
+    ///
+    /// [`compression`]: CompressionOptions
+    /// [`decompression`]: DecompressionOptions
+    pub fn with_options(
+        compression: CompressionOptions,
+        decompression: DecompressionOptions,
+    ) -> Self {
+        Self {
+            compressor: Compressor::from_options(compression),
+            decompressor: Decompressor::from_options(decompression),
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+        }
+    }
+
+    /// Returns the maximum size of the uncompressed data of a frame.
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    /// Sets the maximum size of the uncompressed data of a frame.
+    ///
+    /// Larger frames are rejected with an error, both when encoding and decoding. It
+    /// defaults to 8 MiB, and is capped to [`u32::MAX`].
+    pub fn set_max_frame_size(&mut self, max_frame_size: usize) {
+        self.max_frame_size = max_frame_size.min(u32::MAX as usize);
+    }
+
+    fn frame_too_large(&self, size: usize) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds the maximum of {} bytes",
+                size, self.max_frame_size
+            ),
+        )
+    }
+}
+
+impl Default for LzhamCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder<Bytes> for LzhamCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> io::Result<()> {
+        if item.len() > self.max_frame_size {
+            return Err(self.frame_too_large(item.len()));
+        }
+
+        let start = dst.len();
+        let bound = compress_bound(item.len(), self.compressor.options());
+        dst.resize(start + HEADER_SIZE + bound, 0);
+
+        let mut input = &item[..];
+        let mut len = start + HEADER_SIZE;
+        let result = loop {
+            // The bound is derived from the format, not guaranteed by the native codec, so
+            // the buffer grows instead of leaving the compressor without space.
+            if len == dst.len() {
+                dst.resize(len + bound, 0);
+            }
+
+            let (status, read, written) =
+                self.compressor
+                    .compress_buf(input, &mut dst[len..], CompressionFlush::Finish);
+
+            input = &input[read..];
+            len += written;
+
+            if status.is_success() {
+                break Ok(());
+            } else if status.is_success_or_first_failure() {
+                break Err(Error::Compression(status));
+            }
+        };
+
+        dst.truncate(len);
+        // The compressor must be ready for the next frame, even if this one failed.
+        if !self.compressor.reinit() {
+            dst.truncate(start);
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to reinitialize the compressor",
+            ));
+        }
+
+        if let Err(e) = result {
+            dst.truncate(start);
+            return Err(e.into());
+        }
+
+        let comp_len = (len - start - HEADER_SIZE) as u32;
+        let mut header = &mut dst[start..start + HEADER_SIZE];
+        header.put_u32(comp_len);
+        header.put_u32(item.len() as u32);
+
+        Ok(())
+    }
+}
+
+impl Decoder for LzhamCodec {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        if src.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let mut header = &src[..HEADER_SIZE];
+        let comp_len = header.get_u32() as usize;
+        let uncomp_len = header.get_u32() as usize;
+
+        if uncomp_len > self.max_frame_size {
+            return Err(self.frame_too_large(uncomp_len));
+        }
+
+        // A valid payload is never larger than the bound of the largest frame.
+        let options = CompressionOptions {
+            dict_size_log2: self.decompressor.options().dict_size_log2,
+            ..Default::default()
+        };
+        if comp_len > compress_bound(self.max_frame_size, &options) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("compressed frame of {} bytes is too large", comp_len),
+            ));
+        }
+
+        if src.len() < HEADER_SIZE + comp_len {
+            src.reserve(HEADER_SIZE + comp_len - src.len());
+            return Ok(None);
+        }
+
+        let payload = &src[HEADER_SIZE..HEADER_SIZE + comp_len];
+        let mut output = Vec::with_capacity(uncomp_len);
+        let status = self
+            .decompressor
+            .decompress_to_vec(payload, &mut output, uncomp_len);
+
+        src.advance(HEADER_SIZE + comp_len);
+        if !self.decompressor.reset() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                "failed to reinitialize the decompressor",
+            ));
+        }
+
+        if !status.is_success() {
+            return Err(Error::Decompression(status).into());
+        } else if output.len() != uncomp_len {
+            return Err(Error::SizeMismatch {
+                expected: uncomp_len,
+                actual: output.len(),
+            }
+            .into());
+        }
+
+        Ok(Some(output.into()))
+    }
+}
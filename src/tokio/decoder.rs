@@ -0,0 +1,120 @@
+use super::{CODEC_CALLS_PER_POLL, IN_CHUNK_SIZE};
+use crate::{decompress::Decompressor, DecompressionOptions, Error};
+use ::tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+use pin_project_lite::pin_project;
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// Decompresses the data read from an [`AsyncBufRead`].
+    ///
+    /// The data is decompressed directly into the buffers passed to [`poll_read`], and the
+    /// input is only read as the decompressed data is consumed. It reaches the end of file
+    /// once the compressed stream is finished, and any data following the stream is left in
+    /// the inner reader.
+    ///
+    /// [`poll_read`]: AsyncRead::poll_read
+    #[derive(Debug)]
+    pub struct LzhamDecoder<R> {
+        #[pin]
+        inner: R,
+        decompressor: Decompressor,
+        // Whether the last call wrote output, and the codec may have more without input.
+        pending: bool,
+        done: bool,
+    }
+}
+
+impl<R: AsyncBufRead> LzhamDecoder<R> {
+    /// Creates a new decoder with default [`options`].
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn new(inner: R) -> Self {
+        Self::with_options(inner, DecompressionOptions::default())
+    }
+
+    /// Creates a new decoder with provided [`options`].
+    ///
+    /// [`options`]: DecompressionOptions
+    pub fn with_options(inner: R, options: DecompressionOptions) -> Self {
+        Self {
+            inner,
+            decompressor: Decompressor::from_options(options),
+            pending: false,
+            done: false,
+        }
+    }
+
+    /// Returns a reference to the inner reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner reader.
+    ///
+    /// Reading from it directly corrupts the compressed stream.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Returns a pinned mutable reference to the inner reader.
+    ///
+    /// Reading from it directly corrupts the compressed stream.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut R> {
+        self.project().inner
+    }
+
+    /// Consumes the decoder and returns the inner reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncBufRead> AsyncRead for LzhamDecoder<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+
+        if *this.done || buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+
+        for _ in 0..CODEC_CALLS_PER_POLL {
+            // The end of the input is never signaled to the codec, which would pad it with
+            // zeros and may then decompress a truncated stream endlessly.
+            let input = ready!(this.inner.as_mut().poll_fill_buf(cx))?;
+            if input.is_empty() && !*this.pending {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            let (status, read, written) = this.decompressor.decompress_buf(
+                &input[..input.len().min(IN_CHUNK_SIZE)],
+                buf.initialize_unfilled(),
+                false,
+            );
+
+            this.inner.as_mut().consume(read);
+            buf.advance(written);
+            *this.pending = written > 0;
+
+            if status.is_success() {
+                *this.done = true;
+                return Poll::Ready(Ok(()));
+            } else if status.is_success_or_first_failure() {
+                return Poll::Ready(Err(Error::Decompression(status).into()));
+            } else if written > 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+
+        // Let the other tasks run before continuing.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
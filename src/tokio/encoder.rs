@@ -0,0 +1,204 @@
+use super::{CODEC_CALLS_PER_POLL, IN_CHUNK_SIZE};
+use crate::{
+    compress::{CompressionFlush, Encoder},
+    CompressionOptions,
+};
+use ::tokio::io::AsyncWrite;
+use pin_project_lite::pin_project;
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+
+pin_project! {
+    /// Compresses the data written to it into an [`AsyncWrite`].
+    ///
+    /// The compressed data is buffered, and written to the inner writer as it accepts it.
+    /// [`poll_flush`] makes all the data written so far decodable by emitting a sync flush,
+    /// and [`poll_shutdown`] finishes the stream. The stream is incomplete until it is shut
+    /// down.
+    ///
+    /// [`poll_flush`]: AsyncWrite::poll_flush
+    /// [`poll_shutdown`]: AsyncWrite::poll_shutdown
+    #[derive(Debug)]
+    pub struct LzhamEncoder<W> {
+        #[pin]
+        inner: W,
+        encoder: Encoder,
+        // The position of the output of the codec not written to the inner writer yet.
+        pos: usize,
+        state: State,
+        // Whether data was written since the last flush.
+        dirty: bool,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Accepting data.
+    Writing,
+    /// A sync flush is in progress.
+    Flushing,
+    /// The stream is being finished.
+    Finishing,
+    /// The stream is finished.
+    Done,
+}
+
+impl<W: AsyncWrite> LzhamEncoder<W> {
+    /// Creates a new encoder with default [`options`].
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn new(inner: W) -> Self {
+        Self::with_options(inner, CompressionOptions::default())
+    }
+
+    /// Creates a new encoder with provided [`options`].
+    ///
+    /// [`options`]: CompressionOptions
+    pub fn with_options(inner: W, options: CompressionOptions) -> Self {
+        Self {
+            inner,
+            encoder: Encoder::new(options),
+            pos: 0,
+            state: State::Writing,
+            dirty: false,
+        }
+    }
+
+    /// Returns a reference to the inner writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner writer.
+    ///
+    /// Writing to it directly corrupts the compressed stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Returns a pinned mutable reference to the inner writer.
+    ///
+    /// Writing to it directly corrupts the compressed stream.
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut W> {
+        self.project().inner
+    }
+
+    /// Consumes the encoder and returns the inner writer.
+    ///
+    /// The stream is incomplete unless the encoder was shut down.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Writes the buffered compressed data to the inner writer.
+    fn poll_drain(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+
+        while *this.pos < this.encoder.output().len() {
+            let written = ready!(this
+                .inner
+                .as_mut()
+                .poll_write(cx, &this.encoder.output()[*this.pos..]))?;
+
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+
+            *this.pos += written;
+        }
+
+        Poll::Ready(Ok(()))
+    }
+
+    /// Drives a pending flush or finish until the encoder is writing or done.
+    fn poll_codec(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        for _ in 0..CODEC_CALLS_PER_POLL {
+            ready!(self.as_mut().poll_drain(cx))?;
+
+            let this = self.as_mut().project();
+            let (flush, next) = match this.state {
+                State::Writing | State::Done => return Poll::Ready(Ok(())),
+                State::Flushing => (CompressionFlush::Sync, State::Writing),
+                State::Finishing => (CompressionFlush::Finish, State::Done),
+            };
+
+            let complete = this.encoder.flush(flush)?;
+            *this.pos = 0;
+            if complete {
+                *this.state = next;
+            }
+        }
+
+        // Let the other tasks run before continuing.
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+impl<W: AsyncWrite> AsyncWrite for LzhamEncoder<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        if matches!(self.state, State::Finishing | State::Done) {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::Other,
+                "write after shutdown",
+            )));
+        }
+
+        ready!(self.as_mut().poll_codec(cx))?;
+
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let input = &buf[..buf.len().min(IN_CHUNK_SIZE)];
+
+        for _ in 0..CODEC_CALLS_PER_POLL {
+            // The output must be written out first, so a slow writer applies back-pressure.
+            ready!(self.as_mut().poll_drain(cx))?;
+
+            let this = self.as_mut().project();
+            let read = this.encoder.compress(input)?;
+            *this.pos = 0;
+
+            if read > 0 {
+                *this.dirty = true;
+                return Poll::Ready(Ok(read));
+            }
+        }
+
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.as_mut().project();
+        if *this.state == State::Writing && *this.dirty {
+            *this.state = State::Flushing;
+            *this.dirty = false;
+        }
+
+        ready!(self.as_mut().poll_codec(cx))?;
+
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            ready!(self.as_mut().poll_codec(cx))?;
+
+            match self.state {
+                State::Writing => *self.as_mut().project().state = State::Finishing,
+                _ => break,
+            }
+        }
+
+        self.project().inner.poll_shutdown(cx)
+    }
+}